@@ -0,0 +1,120 @@
+/* Aggregates of a column over the rows that pass a filter (all of them if there's none),
+	leaving out those where the column is NULL, as SQL does (count(*) is that of id, which is never NULL).
+	When the filter is just a range of the column's own index, it's O(log n): the count from the fingers,
+	min/max from the ends of the range and the sum from the sums kept next to the fingers.
+	Otherwise the rows are scanned, over the narrowest range any index gives for the filter.
+	Either way the rows expired but not reaped yet are left out, as they are from the pages: the range then skips them
+	(and takes their values off the sum), which costs O(log n) for each of them.
+ */
+
+use std::sync::Arc;
+
+use crate::*;
+
+#[derive (Copy,Clone,Debug,PartialEq)]
+pub enum Agg { Count, Sum, Min, Max, Avg }
+
+#[derive (Debug, Clone, Default, PartialEq)]
+pub struct Aggregate {
+	pub count: u32,
+	pub sum  : Option<i64>,          // of a numeric column
+	pub min  : Option<Arc<Record>>,  // the rows with the extreme values (of those equal, the lowest id)
+	pub max  : Option<Arc<Record>>   //  (the highest id)
+}
+
+impl Aggregate {
+	pub fn avg (&self)->Option<f64> {
+		self.sum.filter (|_| self.count>0).map (|s| s as f64 / self.count as f64)
+	}
+}
+
+impl Table {
+	// None if the filter would need a scan of more than SCAN_MAX rows
+	pub fn aggregate (&self, col:Column, filter:Option<&Filter>)->Option<Aggregate> {
+		let ix=&self.fields[col as usize];
+		let (a,b,exact) = filter.map_or ((0,self.tot,true), |f| f.range (col, ix, self.tot));
+		let gone=self.expired ();
+
+		if exact {
+			let (_,vals) = ix.null_range (self.tot);
+			let a=cmp::max (a, vals.0);
+			let b=cmp::max (a, cmp::min (b, vals.1));
+			let rows=self.part (ix, a, b, &gone);
+			let n=rows.len ();
+
+			return Some (Aggregate {
+				count: n,
+				sum  : if ix.sums {Some (rows.hidden_pos ().iter ().fold (ix.sum (b).wrapping_sub (ix.sum (a)),
+				                                                           |s,&p| s.wrapping_sub (ix.val (&ix.lookup (p+1).elem))))}
+				       else if matches! (ix.dt, Dt::Str) {None}
+				       else {Some (rows.take (ix, false, 1, n).iter ().map (|r| ix.val (r)).sum ())},
+				min  : rows.take (ix, false, 1, 1).pop (),
+				max  : rows.take (ix, true , 1, 1).pop ()
+			})
+		}
+
+		/* Scan the rows of the index which narrows them down the most */
+		let f=filter.unwrap ();
+		let (by,_) = self.narrowest (f);
+		let rows=self.rows (&self.fields[by as usize], by, filter, &gone)?;
+
+		let mut agg=Aggregate {
+			sum: (!matches! (ix.dt, Dt::Str)).then_some (0),
+			..Default::default ()
+		};
+		for r in rows.take (&self.fields[by as usize], false, 1, rows.len ()).into_iter ().filter (|r| !r.is_null (col)) {
+			agg.count += 1;
+			if let Some (s)=&mut agg.sum {
+				*s = s.wrapping_add (ix.val (&r));
+			}
+			if agg.min.as_ref ().is_none_or (|m| ix.comp (&r,m).is_lt ()) {
+				agg.min = Some (r.clone ());
+			}
+			if agg.max.as_ref ().is_none_or (|m| ix.comp (&r,m).is_gt ()) {
+				agg.max = Some (r);
+			}
+		}
+
+		Some (agg)
+	}
+}
+
+#[cfg (test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn aggregate () {
+		let mut t=Table::new ();
+		for id in 0..1000 {
+			t.add (Record::new (id, (id as i32*7919)%2003-1000, ["ab","abc","b","c"][id as usize % 4])).unwrap ();
+		}
+		for id in (0..1000).step_by (3) {
+			t.rm (id).unwrap ();
+		}
+
+		for expr in [None, Some ("num >= -100 and num < 500"), Some ("id > 100 and id <= 900"), Some ("str like 'ab%'"),
+		             Some ("num > 0 and id < 500"), Some ("str = 'c' or num < -900"), Some ("num = 5000")] {
+			let f=expr.map (|e| Filter::parse (e).unwrap ());
+			let rows:Vec<&Record> = t.iter ().filter (|r| f.as_ref ().is_none_or (|f| f.eval (r))).collect ();
+
+			for col in [Column::Id, Column::Num, Column::Str] {
+				let agg=t.aggregate (col, f.as_ref ()).unwrap ();
+				let key=|r:&Record| match col {
+					Column::Id  => (r.id as i64, [0;SLEN], r.id),
+					Column::Num => (r.num as i64, [0;SLEN], r.id),
+					_           => (0, r.str, r.id)
+				};
+
+				assert_eq! (agg.count as usize, rows.len (), "{expr:?}");
+				assert_eq! (agg.sum, (col!=Column::Str).then (|| rows.iter ().map (|r| key (r).0).sum ()), "{expr:?}");
+				assert_eq! (agg.min.map (|r| r.id), rows.iter ().min_by_key (|r| key (r)).map (|r| r.id), "{expr:?}");
+				assert_eq! (agg.max.map (|r| r.id), rows.iter ().max_by_key (|r| key (r)).map (|r| r.id), "{expr:?}");
+			}
+		}
+
+		let agg=t.aggregate (Column::Num, Some (&Filter::parse ("num >= 0 and num <= 2").unwrap ())).unwrap ();
+		assert_eq! (agg.avg (), Some (agg.sum.unwrap () as f64 / agg.count as f64));
+		assert_eq! (Aggregate::default ().avg (), None);
+	}
+}
@@ -0,0 +1,128 @@
+/* Client configuration, from the command line */
+
+use rustdb::{Column,Filter,DEFAULT_TABLE};
+
+pub const USAGE:&str = r"Usage: clt [options]
+  -a, --addr <host:port>      server to query                           [127.0.0.1:50001]
+  -n, --rows <N>              page size, follows the terminal height if not set
+  -t, --transport <udp|tcp>   how to talk to the server                  [udp]
+  -r, --refresh <live|ms>     live: the server pushes the page whenever the table changes,
+                              ms: re-fetch the page that often (0 - never)
+                              [live with the full screen view, 0 otherwise]
+  -T, --table <name|id>       table to view                              [t]
+  -f, --filter <expr>         only the rows matching it, e.g.: num > 0 and str like 'a%'
+  -g, --group <col[:bucket]>  start with the histogram of the column (id, num or str), the buckets being
+                              ranges of that many numbers or prefixes of that many bytes [about a page]
+  -o, --output <mode>         tui: a full screen view (if on a terminal),
+                              table: the pages printed one after another,
+                              csv: bare records                         [tui]
+  -c, --changes <seq>         print the changes of the table after that one (0 - all kept), as they come
+                              (always over TCP)
+  -R, --repl                  read queries from stdin instead, one per line:
+                              SELECT * FROM t [WHERE <filter>] [ORDER BY id|num|str [ASC|DESC]]
+                                              [LIMIT n] [OFFSET m]
+                              SELECT count(*), sum(num), min(str), ... FROM t [WHERE <filter>]
+                              CREATE TABLE name [COLLATE binary|nocase|natural] [NULLS FIRST|LAST]
+                                                [INDEX abs(num)] [INDEX lower(str)]
+                              DROP TABLE name, SHOW TABLES, SHOW MEMORY name, VERIFY TABLE name,
+                              SHOW REPLICATION (of a replica)
+  -h, --help";
+
+#[derive (Debug, Copy,Clone, PartialEq)]
+pub enum Transport { Udp, Tcp }
+
+#[derive (Debug, Copy,Clone, PartialEq)]
+pub enum Output {
+	Tui,    // full screen, redrawn in place
+	Table,  // the records with the header, the status line and the knob, page after page
+	Csv     // bare `id,num,str` lines, e.g. to pipe somewhere
+}
+
+#[derive (Debug, Copy,Clone, PartialEq)]
+pub enum Refresh {
+	Live,          // subscribed to the changes
+	Every (u32)    // polling, ms (0 - never)
+}
+
+#[derive (Debug)]
+pub struct Config {
+	pub addr     : String,
+	pub rows     : Option<u32>,
+	pub transport: Transport,
+	pub refresh  : Option<Refresh>,
+	pub table    : String,
+	pub filter   : Option<Filter>,
+	pub group    : Option<(Column,u32)>,
+	pub output   : Output,
+	pub changes  : Option<u64>,
+	pub repl     : bool
+}
+
+impl Config {
+	pub fn from_args (args:&[String])->Result<Self,String> {
+		let mut cfg=Config {
+			addr     : "127.0.0.1:50001".to_string (),
+			rows     : None,
+			transport: Transport::Udp,
+			refresh  : None,
+			table    : DEFAULT_TABLE.to_string (),
+			filter   : None,
+			group    : None,
+			output   : Output::Tui,
+			changes  : None,
+			repl     : false
+		};
+
+		let mut it=args.iter ();
+		while let Some (arg)=it.next () {
+			if arg=="-R" || arg=="--repl" {
+				cfg.repl = true;
+				continue
+			}
+			if !["-a","--addr", "-n","--rows", "-t","--transport", "-r","--refresh", "-T","--table", "-f","--filter", "-g","--group", "-o","--output", "-c","--changes"].contains (&arg.as_str ()) {
+				return Err (format! ("Unknown option: {arg}"))
+			}
+			let val=it.next ().ok_or (format! ("Missing value for {arg}"))?;
+			let bad = || format! ("Invalid value for {arg}: {val}");
+
+			match arg.as_str () {
+				"-a"|"--addr"      => cfg.addr = val.clone (),
+				"-n"|"--rows"      => cfg.rows = match val.parse () {
+					Ok (n) if n>0 => Some (n),
+					_             => return Err (bad ())
+				},
+				"-t"|"--transport" => cfg.transport = match val.as_str () {
+					"udp" => Transport::Udp,
+					"tcp" => Transport::Tcp,
+					_     => return Err (bad ())
+				},
+				"-r"|"--refresh"   => cfg.refresh = Some (match val.as_str () {
+					"live" => Refresh::Live,
+					ms     => Refresh::Every (ms.parse ().map_err (|_| bad ())?)
+				}),
+				"-T"|"--table"     => cfg.table = val.clone (),
+				"-f"|"--filter"    => cfg.filter = Some (Filter::parse (val).map_err (|e| format! ("{}: {e}", bad ()))?),
+				"-g"|"--group"     => {
+					let (col,bucket) = val.split_once (':').unwrap_or ((val,"0"));
+					let col=match col {
+						"id"  => Column::Id,
+						"num" => Column::Num,
+						"str" => Column::Str,
+						_     => return Err (bad ())
+					};
+					cfg.group = Some ((col, bucket.parse ().map_err (|_| bad ())?));
+				}
+				"-o"|"--output"    => cfg.output = match val.as_str () {
+					"tui"   => Output::Tui,
+					"table" => Output::Table,
+					"csv"   => Output::Csv,
+					_       => return Err (bad ())
+				},
+				"-c"|"--changes"   => cfg.changes = Some (val.parse ().map_err (|_| bad ())?),
+				_ => unreachable! ()
+			}
+		}
+
+		Ok (cfg)
+	}
+}
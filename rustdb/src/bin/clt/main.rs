@@ -0,0 +1,445 @@
+#![allow (non_snake_case)]
+
+use std::{
+	cmp,
+	env,
+	io,
+	mem,
+	process,
+	slice
+};
+
+use rustdb::*;
+
+mod config;
+mod net;
+mod repl;
+mod term;
+mod view;
+use config::*;
+use net::Conn;
+use term::{Event,Key,Term};
+
+const KEYS:&str = r"Commands:
+  q - sort by column 1 (id)
+  w - sort by column 2 (num)
+  e - sort by column 3 (s)
+  up/down     - 1 element  up/down
+  PgUp/PgDown - N elements up/down
+  Home/End    - to first/to last
+  0..9: change knob position
+  g   - histogram of the sort column / back to the records
+  +/- - coarser/finer buckets of the histogram
+  Ctrl-C/Ctrl-D - quit            ";
+
+const KEEP:i32 = 10_000;  // ms, how often a live UDP client renews its subscription
+const NREC:u32 = 10;     // screen height in terms of rows, when it can't be told from the terminal
+const DECOR:u32 = 4;     // lines taken by the status line, the header, the spacer and the cursor
+const GRP_RFR:i32 = 1000; // ms, how often a live histogram is re-fetched (it isn't pushed)
+
+#[derive (Debug)]
+struct Client {
+	conn: Conn        ,
+	rows: Option<u32> ,  // fixed page size, otherwise it follows the terminal
+	out : Output      ,
+	rfr : Refresh     ,
+	data: Request     ,
+	flt : Option<Filter>,  // sent along with every request
+	npos: u32         ,  // number of possible screen positions
+	kl  : Vec<u32>    ,  // knob limits (in terms of screens)
+	recs: Vec<Record> ,  // the current page
+	grp : Option<Groups> // the histogram view, instead of the records
+}
+
+#[derive (Debug, Default)]
+struct Groups {
+	bucket: u32       ,  // as asked for, 0 - whatever makes about a page of groups
+	used  : u32       ,  // as the server has made them
+	tot   : u32       ,  // number of the groups
+	top   : u32       ,  // the first one shown
+	list  : Vec<Group>
+}
+
+#[derive (Debug, Copy,Clone)]
+enum Cmd {
+	Pos (u32)     ,  // 0..9
+	Col (u32)     ,  // sorting column
+	OneRow (bool) ,  // Up/Down
+	OnePage (bool),  // PageUp/PageDown
+	Home (bool)   ,  // Home/End
+	Group         ,  // histogram on/off
+	Bucket (bool)    // coarser/finer histogram
+}
+
+impl Client {
+	fn new (cfg:&Config)->io::Result<Self> {
+		let mut conn=Conn::open (cfg.transport, &cfg.addr)?;
+		let tbl=table_id (&mut conn, &cfg.table)?;
+
+		Ok (Self {
+			conn,
+			rows: cfg.rows  ,
+			out : cfg.output,
+			rfr : cfg.refresh.unwrap (),
+			flt : cfg.filter.clone (),
+			data: Request {
+				sub: cfg.refresh==Some (Refresh::Live),
+				tbl,
+				..Request::new (Column::Id, NREC)
+			},
+			npos: 0     ,
+			kl  : vec![],
+			recs: vec![],
+			grp : None
+		}.grouped (cfg.group))
+	}
+
+	// Page size: either the requested one or whatever fits the terminal right now
+	fn page (&self)->u32 {
+		self.rows.unwrap_or_else (|| {
+			let decor=if self.out==Output::Tui {view::DECOR} else {DECOR};
+			term::size ().map_or (NREC, |(rows,_)| cmp::max (1, rows.saturating_sub (decor)))
+		})
+	}
+
+	// Start with the histogram of the column
+	fn grouped (mut self, group:Option<(Column,u32)>)->Self {
+		if let Some ((col,bucket))=group {
+			self.data.col = col;
+			self.grp = Some (Groups { bucket, ..Default::default () });
+		}
+		self
+	}
+
+	fn submit (&mut self, cmd:Cmd)->io::Result<()> {
+		if self.grp.is_some () {
+			return self.submit_grp (cmd)
+		}
+
+		let r=&mut self.data;
+		r.cur = Cursor::Pos;
+
+		use Cmd::*;
+		match cmd {
+			Pos (pos) => {                               // new knob position [0..9]
+				if pos < self.npos {
+					r.CS = self.kl[pos as usize];
+				}
+				else {
+					return Ok (())
+				}
+			}
+			Col (col) => {                               // new column to sort
+				if r.col as u32==col {
+					r.bw ^= true;
+				}
+				r.col = match col {
+					0=>Column::Id,
+					1=>Column::Num,
+					2=>Column::Str,
+					_=>unreachable! ()
+				};
+			}
+			/* Moves are relative to the rows on the screen, so that they don't skip or repeat any
+			   while the table changes. From an empty page, there's nothing to go by but the position. */
+			OneRow (up) | OnePage (up) if !self.recs.is_empty () => {
+				let (first,last) = (self.recs[0], self.recs[self.recs.len ()-1]);
+				(r.cur, r.key) = match (matches! (cmd, OneRow (_)), up) {
+					(true , true ) => (Cursor::Before, last ),  // the rows up to the last one
+					(true , false) => (Cursor::After , first),
+					(false, true ) => (Cursor::Before, first),  // the page before
+					(false, false) => (Cursor::After , last )
+				};
+			}
+			OneRow (up) => {                             // Up/Down 1-row
+				if up {
+					if r.CS>0 {
+						r.CS -= 1 ;
+					}
+				}
+				else {
+					if r.CS < r.NS-1 {
+						r.CS += 1;
+					}
+				}
+			}
+			OnePage (up) => {                           // Up/Down Page
+				if up {
+					if r.CS < r.N-1 {
+						r.CS=0
+					}
+					else {
+						r.CS -= r.N-1;
+					}
+				}
+				else {
+					if r.CS+r.N > r.NS {
+						r.CS = r.NS-1;
+					}
+					else {
+						r.CS += r.N-1;
+					}
+				}
+			}
+			Home (up) => r.CS = if up {0} else {r.NS-1}, // Home/End
+			Group     => self.grp = Some (Groups::default ()),
+			Bucket (_) => return Ok (())
+		}
+
+		self.fire ()
+	}
+
+	/* In the histogram view the moves scroll the groups (all of them are at hand), the columns are
+	   switched without the direction, and the buckets are made twice as wide or narrow (for str,
+	   a byte shorter or longer prefixes) */
+	fn submit_grp (&mut self, cmd:Cmd)->io::Result<()> {
+		let N=self.page ();
+		let g=self.grp.as_mut ().unwrap ();
+		let last=(g.list.len () as u32).saturating_sub (N);
+
+		use Cmd::*;
+		match cmd {
+			Pos (_)         => return Ok (()),
+			Col (col)       => {
+				self.data.col = [Column::Id, Column::Num, Column::Str][col as usize];
+				(g.bucket, g.top) = (0, 0);
+			}
+			OneRow (up)     => g.top = if up {g.top.saturating_sub (1)} else {cmp::min (g.top+1, last)},
+			OnePage (up)    => g.top = if up {g.top.saturating_sub (N)} else {cmp::min (g.top+N, last)},
+			Home (up)       => g.top = if up {0} else {last},
+			Group           => {
+				self.grp = None;
+				self.data.cur = Cursor::Pos;  // the page may have moved away meanwhile
+			}
+			Bucket (coarse) => g.bucket = match (self.data.col, coarse) {
+				(Column::Str, true ) => cmp::max (1, g.used.saturating_sub (1)),
+				(Column::Str, false) => cmp::min (SLEN as u32, g.used+1),
+				(_          , true ) => g.used.saturating_mul (2),
+				(_          , false) => cmp::max (1, g.used/2)
+			}
+		}
+
+		self.fire ()
+	}
+
+	fn fire (&mut self)->io::Result<()> {
+		self.data.N = self.page ();
+		self.data.tag = self.data.tag.wrapping_add (1);
+		self.kl.resize (self.data.N as usize, 0);
+
+		// pages pushed meanwhile have an older tag, and are dropped
+		if let Some (g)=&mut self.grp {
+			let req=GroupReq::new (self.data.tbl, self.data.col, g.bucket, self.data.N, self.data.tag);
+			let buf=self.conn.call (&req.to_bytes (), self.data.tag, RESP_SZ + GROUP_SZ*GROUP_MAX as usize)?;
+			let resp=Response::from_bytes (&buf).filter (|r| r.ok)
+			         .ok_or (io::Error::new (io::ErrorKind::InvalidData, "bad response"))?;
+
+			(g.used, g.tot, g.list) = (resp.CS, resp.NS, Group::parse (&buf));
+			g.top = cmp::min (g.top, (g.list.len () as u32).saturating_sub (self.data.N));
+			return Ok (())
+		}
+
+		/* Send request, receive Response */
+		let p=&self.data as *const Request as *const u8;
+		let bytes = unsafe {
+			slice::from_raw_parts (p, mem::size_of::<Request> ())
+		};
+		let mut msg=bytes.to_vec ();
+		if let Some (f)=&self.flt {
+			msg.extend (f.to_bytes ());
+		}
+		let buf=self.conn.call (&msg, self.data.tag, RESP_SZ + REC_SZ*self.data.N as usize)?;
+
+		self.apply (&buf)
+	}
+
+	// How long to wait for the keys, before re-fetching (or renewing the subscription)
+	fn timeout (&self)->i32 {
+		match (self.rfr, &self.conn) {
+			(Refresh::Live, _) if self.grp.is_some () => GRP_RFR,
+			(Refresh::Live, Conn::Udp (..)) => KEEP,
+			(Refresh::Live, Conn::Tcp (..)) => -1,
+			(Refresh::Every (0), _)         => -1,
+			(Refresh::Every (ms), _)        => cmp::min (ms, i32::MAX as u32) as i32
+		}
+	}
+
+	// A page pushed by the server: taken unless it's for an older request
+	fn pushed (&mut self)->io::Result<bool> {
+		let buf=self.conn.recv (RESP_SZ + REC_SZ*self.data.N as usize)?;
+		if Response::from_bytes (&buf).is_none_or (|r| r.tag!=self.data.tag) {
+			return Ok (false)
+		}
+
+		self.apply (&buf).map (|_| true)
+	}
+
+	fn apply (&mut self, buf:&[u8])->io::Result<()> {
+		let Some (resp)=Response::from_bytes (buf) else {
+			return Err (io::Error::new (io::ErrorKind::InvalidData, "bad response"))
+		};
+
+		/* Process Response */
+		if !resp.ok {
+			return Err (io::Error::other ("Request refused: bad parameters, or a filter that needs too long a scan"))
+		}
+		let (NS,N) = (resp.NS,self.data.N);
+
+		/* Fill knob data */
+		self.npos = if NS>N {N} else {NS};
+		for i in 1..self.npos {
+			self.kl[i as usize] = scale (i, NS-1, self.npos-1);
+		}
+
+		self.recs = Response::records (buf);
+
+		(self.data.CS, self.data.NS) = (resp.CS, resp.NS);
+
+		// Re-fetching starts from the same top row, wherever it has moved to
+		if let Some (first)=self.recs.first () {
+			(self.data.cur, self.data.key) = (Cursor::At, *first);
+		}
+
+		Ok (())
+	}
+}
+
+// The id of the table by the name, as the server lists them (an id is taken as is)
+fn table_id (conn:&mut Conn, table:&str)->io::Result<u32> {
+	if let Ok (id)=table.parse () {
+		return Ok (id)
+	}
+
+	let buf=conn.call (&Query::msg ("show tables", 0), 0, 64*1024)?;
+	let list=String::from_utf8_lossy (buf.get (RESP_SZ..).unwrap_or_default ()).to_string ();
+	list.lines ().skip (1).find_map (|l| match l.split_whitespace ().collect::<Vec<_>> ()[..] {
+		[id,name,_] if name==table => id.parse ().ok (),
+		_                          => None
+	}).ok_or (io::Error::new (io::ErrorKind::NotFound, format! ("No table {table}")))
+}
+
+// The changes of the table, printed as they come until the server hangs up
+fn follow (conn:&mut Conn, table:&str, from:u64)->io::Result<()> {
+	let tbl=table_id (conn, table)?;
+	let buf=conn.call (&ChangesReq::new (tbl, 0, from, false, 1).to_bytes (), 1, 64*1024)?;
+	if !Response::from_bytes (&buf).is_some_and (|r| r.ok) {
+		let why=String::from_utf8_lossy (buf.get (RESP_SZ..).unwrap_or_default ()).to_string ();
+		return Err (io::Error::other (if why.is_empty () {"Refused".to_string ()} else {why}))
+	}
+
+	loop {
+		let buf=match conn.recv (REC_SZ*4) {
+			Ok (buf) => buf,
+			Err (e) if e.kind ()==io::ErrorKind::UnexpectedEof => return Ok (()),
+			Err (e)  => return Err (e)
+		};
+		if let Some (ev)=rustdb::Event::decode (&buf) {
+			println! ("{ev}");
+		}
+	}
+}
+
+fn main () {
+	let args:Vec<String> = env::args ().skip (1).collect ();
+	if args.iter ().any (|a| a=="-h" || a=="--help") {
+		println! ("{USAGE}\n\n{KEYS}");
+		return
+	}
+	let mut cfg=Config::from_args (&args).unwrap_or_else (|e| {
+		eprintln! ("{e}\n\n{USAGE}");
+		process::exit (2);
+	});
+	if cfg.output==Output::Tui && (cfg.repl || !term::is_tty ()) {
+		cfg.output = Output::Table;
+	}
+
+	if cfg.repl {
+		let res=Conn::open (cfg.transport, &cfg.addr).and_then (|mut conn| repl::run (&mut conn, cfg.output));
+		if let Err (e)=res {
+			eprintln! ("{}: {e}", cfg.addr);
+			process::exit (1);
+		}
+		return
+	}
+	if let Some (from)=cfg.changes {
+		let res=Conn::open (Transport::Tcp, &cfg.addr).and_then (|mut conn| follow (&mut conn, &cfg.table, from));
+		if let Err (e)=res {
+			eprintln! ("{}: {e}", cfg.addr);
+			process::exit (1);
+		}
+		return
+	}
+	// Pages printed one after another would just scroll away with every change
+	cfg.refresh.get_or_insert (if cfg.output==Output::Tui {Refresh::Live} else {Refresh::Every (0)});
+
+	let mut clt=Client::new (&cfg).unwrap_or_else (|e| {
+		eprintln! ("{}: {e}", cfg.addr);
+		process::exit (1);
+	});
+
+	let term=Term::new (cfg.output==Output::Tui);
+	let res=run (&mut clt, &term);
+	drop (term);  // before anything is printed
+
+	if let Err (e)=res {
+		eprintln! ("{e}");
+		process::exit (1);
+	}
+}
+
+fn run (clt:&mut Client, term:&Term)->io::Result<()> {
+	clt.fire ()?;
+	clt.show ();
+
+	/* Besides the keys: pushed pages when live, or the timer to re-fetch (or to renew the subscription) */
+	loop {
+		let keys=match term.wait (clt.conn.fd (), clt.timeout ())? {
+			Event::Keys (keys) => keys,
+			Event::Ready       => {
+				if clt.pushed ()? {
+					clt.show ();
+				}
+				continue
+			}
+			Event::Timeout     => {
+				clt.fire ()?;
+				clt.show ();
+				continue
+			}
+			Event::Signal      => vec![]
+		};
+		if term::resized () {
+			clt.fire ()?;
+			clt.show ();
+		}
+
+		for key in keys {
+			use Cmd::*;
+			let cmd=match key {
+				Key::Quit      => return Ok (()),
+				Key::Char (c@b'0'..=b'9') => Pos (u32::from (c-b'0')),
+				Key::Char (b'q') => Col (0),
+				Key::Char (b'w') => Col (1),
+				Key::Char (b'e') => Col (2),
+				Key::Char (b'g') => Group,
+				Key::Char (b'+'|b'=') => Bucket (true),
+				Key::Char (b'-') => Bucket (false),
+				Key::Up        => OneRow (true),
+				Key::Down      => OneRow (false),
+				Key::PgUp      => OnePage (true),
+				Key::PgDown    => OnePage (false),
+				Key::Home      => Home (true),
+				Key::End       => Home (false),
+				_ => {
+					if clt.out!=Output::Tui {  // the TUI has them in the status line
+						println! ("{KEYS}");
+					}
+					continue
+				}
+			};
+
+			clt.submit (cmd)?;
+			clt.show ();
+		}
+	}
+}
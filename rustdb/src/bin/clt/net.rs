@@ -0,0 +1,87 @@
+/* Request/response exchange with the server over either transport */
+
+use std::{
+	io::{self,Read,Write},
+	net::{SocketAddr,TcpStream,ToSocketAddrs,UdpSocket},
+	os::fd::{AsRawFd,RawFd}
+};
+
+use rustdb::{Response,FRAME_HDR};
+
+use crate::config::Transport;
+
+#[derive (Debug)]
+pub enum Conn {
+	Udp (UdpSocket, SocketAddr),
+	Tcp (TcpStream)
+}
+
+impl Conn {
+	pub fn open (tr:Transport, addr:&str)->io::Result<Self> {
+		let addr=addr.to_socket_addrs ()?.next ()
+		         .ok_or (io::Error::new (io::ErrorKind::NotFound, "no address"))?;
+
+		Ok (match tr {
+			Transport::Udp => Conn::Udp (UdpSocket::bind ("0.0.0.0:0")?, addr),
+			Transport::Tcp => {
+				let s=TcpStream::connect (addr)?;
+				s.set_nodelay (true)?;
+				Conn::Tcp (s)
+			}
+		})
+	}
+
+	// To wait on, along with the terminal
+	pub fn fd (&self)->RawFd {
+		match self {
+			Conn::Udp (sock,_) => sock.as_raw_fd (),
+			Conn::Tcp (sock)   => sock.as_raw_fd ()
+		}
+	}
+
+	/* Send a request and wait for its response (at most max bytes long), that is the one with the same tag.
+	   Whatever the server has pushed for the previous requests meanwhile is skipped. */
+	pub fn call (&mut self, req:&[u8], tag:u32, max:usize)->io::Result<Vec<u8>> {
+		match self {
+			Conn::Udp (sock,addr) => { sock.send_to (req, *addr)?; }
+			Conn::Tcp (sock)      => {
+				let mut msg=(req.len () as u32).to_ne_bytes ().to_vec ();
+				msg.extend_from_slice (req);
+				sock.write_all (&msg)?;
+			}
+		}
+
+		loop {
+			let buf=self.recv (max)?;
+			if Response::from_bytes (&buf).is_some_and (|r| r.tag==tag) {
+				return Ok (buf)
+			}
+		}
+	}
+
+	// A single message from the server, be it a reply or a pushed page
+	pub fn recv (&mut self, max:usize)->io::Result<Vec<u8>> {
+		match self {
+			Conn::Udp (sock,_) => {
+				let mut buf=vec![0;max];
+				let (amt,_) = sock.recv_from (&mut buf)?;
+				buf.truncate (amt);
+
+				Ok (buf)
+			}
+			Conn::Tcp (sock) => {
+				let mut hdr=[0u8;FRAME_HDR];
+				sock.read_exact (&mut hdr)?;
+				let len=u32::from_ne_bytes (hdr) as usize;
+				if len>max {
+					return Err (io::Error::new (io::ErrorKind::InvalidData, "oversized response"))
+				}
+
+				let mut buf=vec![0;len];
+				sock.read_exact (&mut buf)?;
+
+				Ok (buf)
+			}
+		}
+	}
+}
@@ -0,0 +1,82 @@
+/* Queries and statements typed (or piped) in one per line, each answered with a table or CSV lines */
+
+use std::io::{self,BufRead,IsTerminal,Write};
+
+use rustdb::*;
+
+use crate::{net::Conn,view,Output};
+
+const MAX_ANS:usize = 64*1024;  // longest answer, be it the records or an error message
+
+pub fn run (conn:&mut Conn, out:Output)->io::Result<()> {
+	let prompt=io::stdin ().is_terminal ();
+	let mut tag=0u32;
+
+	let mut lines=io::stdin ().lock ().lines ();
+	loop {
+		if prompt {
+			print! ("> ");
+			io::stdout ().flush ()?;
+		}
+		let Some (line)=lines.next () else {
+			break
+		};
+		let line=line?;
+		let line=line.trim ();
+		if line.is_empty () {
+			continue
+		}
+		if line.eq_ignore_ascii_case ("quit") || line.eq_ignore_ascii_case ("exit") {
+			break
+		}
+
+		// Checked here as well, for the errors to come without a round trip, and to tell the rows from the text
+		let st=match Statement::parse (line) {
+			Ok (st) => st,
+			Err (e) => {
+				eprintln! ("{e}");
+				continue
+			}
+		};
+
+		tag = tag.wrapping_add (1);
+		let buf=conn.call (&Query::msg (line, tag), tag, MAX_ANS)?;
+		let Some (resp)=Response::from_bytes (&buf) else {
+			return Err (io::Error::new (io::ErrorKind::InvalidData, "bad response"))
+		};
+		if !resp.ok {
+			eprintln! ("{}", String::from_utf8_lossy (&buf[RESP_SZ..]));
+			continue
+		}
+
+		if matches! (&st, Statement::Select (q) if q.aggs.is_empty ()) {
+			show (out, &Response::records (&buf), resp.CS, resp.NS);
+		}
+		else {
+			print! ("{}", String::from_utf8_lossy (&buf[RESP_SZ..]));
+		}
+	}
+
+	Ok (())
+}
+
+// The rows from the offset on, out of the total of the matching ones
+fn show (out:Output, recs:&[Record], offset:u32, tot:u32) {
+	if out==Output::Csv {
+		for r in recs {
+			println! ("{}", view::csv (r));
+		}
+		return
+	}
+
+	println! ("  id          num        str");
+	for r in recs {
+		println! ("  {:<8}  {:>8}  {:>6}", r.id, r.text (Column::Num), r.text (Column::Str));
+	}
+	if recs.is_empty () {
+		println! ("(none)/{tot}");
+	}
+	else {
+		println! ("({}..{})/{tot}", offset as u64+1, offset as u64+recs.len () as u64);
+	}
+}
@@ -0,0 +1,178 @@
+/* Terminal handling: raw mode (restored on drop), the size, resize notifications and key input */
+
+use std::{
+	io::{self,Write},
+	mem,
+	os::fd::RawFd,
+	sync::atomic::{AtomicBool,Ordering}
+};
+
+use libc::*;
+
+static RESIZED:AtomicBool = AtomicBool::new (false);
+
+extern "C" fn on_winch (_:c_int) {
+	RESIZED.store (true, Ordering::SeqCst);
+}
+
+// Was the terminal resized since the last call?
+pub fn resized ()->bool {
+	RESIZED.swap (false, Ordering::SeqCst)
+}
+
+// (rows, columns) of the terminal on stdout, if it's a terminal at all
+pub fn size ()->Option<(u32,u32)> {
+	let mut ws:winsize = unsafe { mem::zeroed () };
+	if unsafe { ioctl (STDOUT_FILENO, TIOCGWINSZ, &mut ws) }!=0 || ws.ws_row==0 {
+		return None
+	}
+	Some ((ws.ws_row as u32, ws.ws_col as u32))
+}
+
+pub fn is_tty ()->bool {
+	unsafe { isatty (STDOUT_FILENO)==1 }
+}
+
+#[derive (Debug, Copy,Clone, PartialEq)]
+pub enum Key {
+	Char (u8),
+	Up, Down, PgUp, PgDown, Home, End,
+	Quit,      // Ctrl-C, Ctrl-D
+	Unknown
+}
+
+#[derive (Debug)]
+pub enum Event {
+	Keys (Vec<Key>),
+	Ready,            // the other fd has something to read
+	Timeout,
+	Signal
+}
+
+/* Puts stdin into the raw mode (and optionally stdout onto the alternate screen) while alive */
+pub struct Term {
+	saved : Option<termios>,  // none if stdin isn't a terminal
+	screen: bool
+}
+
+impl Term {
+	pub fn new (screen:bool)->Self {
+		let mut tio:termios = unsafe { mem::zeroed () };
+		let saved=if unsafe { tcgetattr (STDIN_FILENO, &mut tio) }==0 {
+			let old=tio;
+
+			// ISIG is off as well, Ctrl-C comes as a key so that the terminal is always restored
+			tio.c_lflag &= !(ICANON|ECHO|ISIG);
+			tio.c_cc[VMIN]  = 1;
+			tio.c_cc[VTIME] = 0;
+			unsafe { tcsetattr (STDIN_FILENO, TCSANOW, &tio) };
+
+			Some (old)
+		}
+		else {
+			None
+		};
+
+		// No SA_RESTART: a resize has to interrupt the wait for input
+		unsafe {
+			let mut sa:sigaction = mem::zeroed ();
+			sa.sa_sigaction = on_winch as *const () as sighandler_t;
+			sigaction (SIGWINCH, &sa, std::ptr::null_mut ());
+		}
+
+		if screen {
+			print! ("\x1B[?1049h\x1B[?25l");  // alternate screen, hidden cursor
+			io::stdout ().flush ().unwrap ();
+		}
+
+		Self { saved, screen }
+	}
+
+	/* Wait up to timeout ms (-1 is forever) for the keys, or for the other fd to become readable.
+	   A signal (e.g. a resize) interrupts the wait as well. */
+	pub fn wait (&self, other:RawFd, timeout:i32)->io::Result<Event> {
+		let mut fds=[
+			pollfd { fd: STDIN_FILENO, events: POLLIN, revents: 0 },
+			pollfd { fd: other       , events: POLLIN, revents: 0 }
+		];
+		match unsafe { poll (fds.as_mut_ptr (), 2, timeout) } {
+			0 => return Ok (Event::Timeout),
+			n if n<0 => {
+				let e=io::Error::last_os_error ();
+				return if e.kind ()==io::ErrorKind::Interrupted {Ok (Event::Signal)} else {Err (e)}
+			}
+			_ => {}
+		}
+		if fds[0].revents==0 {
+			return Ok (Event::Ready)
+		}
+
+		let mut buf=[0u8;64];
+		let n=unsafe { read (STDIN_FILENO, buf.as_mut_ptr () as *mut c_void, buf.len ()) };
+		match n {
+			0          => Ok (Event::Keys (vec![Key::Quit])),  // EOF
+			n if n<0   => Err (io::Error::last_os_error ()),
+			n          => Ok (Event::Keys (parse (&buf[..n as usize])))
+		}
+	}
+}
+
+impl Drop for Term {
+	fn drop (&mut self) {
+		if self.screen {
+			print! ("\x1B[?25h\x1B[?1049l");
+			let _=io::stdout ().flush ();
+		}
+		if let Some (tio)=self.saved {
+			unsafe { tcsetattr (STDIN_FILENO, TCSANOW, &tio) };
+		}
+	}
+}
+
+// Split a chunk of input into keys, escape sequences come whole within a read
+fn parse (mut buf:&[u8])->Vec<Key> {
+	use Key::*;
+
+	let mut keys=vec![];
+	while let Some (&c)=buf.first () {
+		let (key,len) = match buf {
+			[27, b'[', b'A', ..]       |
+			[27, b'O', b'A', ..]       => (Up    , 3),
+			[27, b'[', b'B', ..]       |
+			[27, b'O', b'B', ..]       => (Down  , 3),
+			[27, b'[', b'H', ..]       |
+			[27, b'O', b'H', ..]       => (Home  , 3),
+			[27, b'[', b'F', ..]       |
+			[27, b'O', b'F', ..]       => (End   , 3),
+			[27, b'[', b'5', b'~', ..] => (PgUp  , 4),
+			[27, b'[', b'6', b'~', ..] => (PgDown, 4),
+			[27, b'[', b'1', b'~', ..] => (Home  , 4),
+			[27, b'[', b'4', b'~', ..] => (End   , 4),
+			[27, b'[', ..]             => {
+				// skip an unknown CSI sequence up to its final byte
+				let n=buf[2..].iter ().position (|c| (0x40..=0x7E).contains (c)).map_or (buf.len (), |p| p+3);
+				(Unknown, n)
+			}
+			[3, ..] | [4, ..]          => (Quit   , 1),
+			_                          => (Char (c), 1)
+		};
+
+		keys.push (key);
+		buf = &buf[len..];
+	}
+
+	keys
+}
+
+#[cfg (test)]
+mod tests {
+	use super::*;
+	use Key::*;
+
+	#[test]
+	fn keys () {
+		assert_eq! (parse (b"q\x1B[A\x1B[6~\x1BOH\x03"), [Char (b'q'), Up, PgDown, Home, Quit]);
+		assert_eq! (parse (b"\x1B[1;5Cw"), [Unknown, Char (b'w')]);
+		assert_eq! (parse (b"\x1B["), [Unknown]);
+	}
+}
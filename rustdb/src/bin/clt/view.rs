@@ -0,0 +1,215 @@
+/* Showing the current page in one of the output modes */
+
+use std::{
+	cmp,
+	fmt::Write as _,
+	io::{self,Write},
+	str
+};
+
+use rustdb::*;
+
+use crate::{term,Client,Groups,Output};
+
+pub const DECOR:u32 = 2;  // lines the TUI takes besides the records: the header and the status line
+
+const HINT:&str = "q/w/e: sort  ↑↓ PgUp PgDn Home End: move  0-9: knob  g: groups  ^C: quit";
+const GHINT:&str = "q/w/e: column  ↑↓ PgUp PgDn Home End: move  +/-: coarser/finer  g: rows  ^C: quit";
+const BAR:u32 = 40;  // columns the longest bar of a histogram takes at most
+
+impl Client {
+	pub fn show (&self) {
+		if let Some (g)=&self.grp {
+			return self.show_groups (g)
+		}
+		match self.out {
+			Output::Tui   => self.draw (),
+			Output::Table => self.print_table (),
+			Output::Csv   => self.print_csv ()
+		}
+	}
+
+	// Elements (a..b) out of tot being shown, as counted in the sort direction
+	fn range (&self)->(u32,u32,u32) {
+		let (CS,NS) = (self.data.CS, self.data.NS);
+		// the page is short of N when the whole table is on it
+		let N=cmp::min (self.data.N, self.recs.len () as u32);
+		if N==0 {
+			return (0,0,0)
+		}
+
+		let mut a = CS + 1;
+		let mut b = CS + N;
+		let   tot = NS + N - 1;
+		if self.data.bw {
+			a = tot-a+1;
+			b = tot-b+1;
+		}
+		(a,b,tot)
+	}
+
+	// Sort arrows over the columns
+	fn arrows (&self)->[char; Column::NumCol as usize] {
+		let mut s = [' '; Column::NumCol as usize];
+		s[self.data.col as usize] = if self.data.bw {'↑'} else {'↓'};
+		s
+	}
+
+	/* Full screen: header, records with the scrollbar along the right edge, status line at the bottom */
+	fn draw (&self) {
+		let (rows,cols) = term::size ().unwrap_or ((self.data.N+DECOR, 80));
+		let (N,CS,NS) = (self.data.N, self.data.CS, self.data.NS);
+		let (a,b,tot) = self.range ();
+
+		/* The scrollbar's thumb is as long as the page is, relative to the whole table */
+		let (ks,kl) = if NS<=1 {
+			(0,N)
+		}
+		else {
+			let kl=cmp::max (1, (N as u64*N as u64 / tot as u64) as u32);
+			(((CS as u64*(N-kl) as u64 + (NS as u64-1)/2) / (NS as u64-1)) as u32, kl)
+		};
+
+		let mut scr=String::from ("\x1B[H");
+		use Column::*;
+		let s=self.arrows ();
+		let _=write! (scr, "\x1B[1m{} id        {} num      {} str\x1B[0m\x1B[K\r\n",
+		              s[Id as usize], s[Num as usize], s[Str as usize]);
+
+		for i in 0..N {
+			if let Some (r)=self.recs.get (i as usize) {
+				let _=write! (scr, "  {:<8}  {:>8}  {:>6}", r.id, r.text (Column::Num), r.text (Column::Str));
+			}
+			let bar=if i>=ks && i<ks+kl {'█'} else {'░'};
+			let _=write! (scr, "\x1B[K\x1B[{cols}G{bar}\r\n");
+		}
+
+		/* Status line in reverse video, cut or padded to the width */
+		let mut st=format! (" ({a}..{b})/{tot}   ");
+		if let Some (f)=&self.flt {
+			st += &format! ("where {f}   ");
+		}
+		if (st.chars ().count () + HINT.chars ().count ()) < cols as usize {
+			st += HINT;
+		}
+		let st:String = st.chars ().chain (std::iter::repeat (' ')).take (cols as usize).collect ();
+		let _=write! (scr, "\x1B[{rows};1H\x1B[7m{st}\x1B[0m");
+
+		let mut out=io::stdout ().lock ();
+		let _=out.write_all (scr.as_bytes ()).and_then (|_| out.flush ());
+	}
+
+	/* Page after page, with the knob drawn next to the records */
+	fn print_table (&self) {
+		let (CS,NS,N) = (self.data.CS, self.data.NS, self.data.N);
+		let Nrec=self.recs.len () as u32;
+
+		/* Find after which pos CS is */
+		let mut pos=0;
+		for i in (0..self.npos).rev () {
+			if CS >= self.kl[i as usize] {
+				pos=i;
+				break;
+			}
+		};
+
+		/* Setup knob drawing */
+		let kl=if N+1>NS {N-NS+1} else {1};
+		let mut knob = vec!['░';N as usize];
+		for i in 0..N {
+			if i>=pos && i<pos+kl {
+				knob[i as usize] = '▒';
+			}
+		}
+		let (a,b,tot) = self.range ();
+		println! ("Showing elements: ({a}..{b})/{tot}");
+
+		use Column::*;
+		let s=self.arrows ();
+		println! ("{} id        {} num      {} str",
+		          s[Id as usize],s[Num as usize],s[Str as usize]);
+
+		for i in 0..N {
+			if i<Nrec {
+				let i=i as usize;
+				let r=&self.recs[i];
+
+				println! ("  {:<8}  {:>8}  {:>6}   {:4}",
+				          r.id, r.text (Column::Num), r.text (Column::Str),
+				          knob[i]);
+			}
+			else {
+				println! ("--blank--                       {}",knob[i as usize]);
+			}
+		}
+		println! ();
+	}
+
+	fn print_csv (&self) {
+		for r in &self.recs {
+			println! ("{}", csv (r));
+		}
+		println! ();
+	}
+
+	/* The histogram: a line per group with its count and a bar, as long as the page is */
+	fn show_groups (&self, g:&Groups) {
+		let col=self.data.col;
+		let key=|r:&Record| match col {
+			Column::Str if !r.is_null (col) => String::from_utf8_lossy (&r.str[..cmp::min (g.used as usize, SLEN)]).to_string (),
+			_                               => r.text (col)
+		};
+		let page=g.list.iter ().skip (g.top as usize).take (self.data.N as usize);
+
+		if self.out==Output::Csv {
+			for gr in page {
+				println! ("{},{}", key (&gr.key), gr.count);
+			}
+			println! ();
+			return
+		}
+
+		let max=g.list.iter ().map (|gr| gr.count).max ().unwrap_or (1);
+		let (a,b) = (g.top+1, g.top + cmp::min (self.data.N, (g.list.len () as u32).saturating_sub (g.top)));
+		let name=["id","num","str"][col as usize];
+		let mut st=format! (" groups {a}..{b}/{}   by {name}, bucket {}   ", g.tot, g.used);
+		if let Some (f)=&self.flt {
+			st += &format! ("(not filtered by {f})   ");
+		}
+
+		let mut lines=vec![format! ("  {name:<10}  count")];
+		for i in 0..self.data.N as usize {
+			lines.push (g.list.get (g.top as usize + i).map_or (String::new (), |gr| {
+				let w=(gr.count as u64*BAR as u64).div_ceil (max as u64) as usize;
+				format! ("  {:<10}  {:>8}  {}", key (&gr.key), gr.count, "█".repeat (w))
+			}));
+		}
+
+		let out=if self.out==Output::Tui {
+			let (rows,cols) = term::size ().unwrap_or ((self.data.N+DECOR, 80));
+			if (st.chars ().count () + GHINT.chars ().count ()) < cols as usize {
+				st += GHINT;
+			}
+			let st:String = st.chars ().chain (std::iter::repeat (' ')).take (cols as usize).collect ();
+
+			let mut scr=format! ("\x1B[H\x1B[1m{}\x1B[0m\x1B[K\r\n", lines[0]);
+			for l in &lines[1..] {
+				let _=write! (scr, "{l}\x1B[K\r\n");
+			}
+			let _=write! (scr, "\x1B[{rows};1H\x1B[7m{st}\x1B[0m");
+			scr
+		}
+		else {
+			lines.join ("\n") + "\n" + st.trim () + "\n\n"
+		};
+
+		let mut o=io::stdout ().lock ();
+		let _=o.write_all (out.as_bytes ()).and_then (|_| o.flush ());
+	}
+}
+
+// A record as a CSV line, with the NULLs the way the server loads them
+pub fn csv (r:&Record)->String {
+	let field=|col| if r.is_null (col) {CSV_NULL.to_string ()} else {r.text (col)};
+	format! ("{},{},{}", r.id, field (Column::Num), field (Column::Str))
+}
@@ -0,0 +1,229 @@
+/* Server configuration: the defaults, overridden by the config file (if any),
+   overridden by the command line */
+
+use std::{
+	fs,
+	net::SocketAddr,
+	path::PathBuf,
+	time::Duration
+};
+
+use rustdb::{Collation,Nulls};
+
+pub const USAGE:&str = r"Usage: srv [options]
+  -c, --config <file>     read the options from a file, one `key = value` per line
+                          (keys are the long option names below)
+  -l, --listen <addr>     address to serve on                                [127.0.0.1:50001]
+  -d, --data <src>        initial data: empty, gen:<N>, csv:<file> or snapshot:<file>  [gen:20]
+  -f, --fill <N|random>   rows to generate on top of the initial data        [random]
+  -w, --writers <N>       number of synthetic writers, 0 for none            [10]
+  -t, --ttl <secs>        the rows the writers add expire after that long
+  -s, --snapshot <file>   flush the table there on shutdown
+  -C, --collation <c>     order of the str column: binary, nocase (ASCII) or natural  [binary]
+  -N, --nulls <first|last>  where the indexes put the NULLs                  [last]
+  -m, --memory <MiB>      cap of the default table's memory, the adds over it are refused
+  -S, --seed <N>          of the random data, levels and writers, to replay a run  [random]
+  -P, --primary <addr>    run as a read-only replica of that server's default table
+                          (--data, --fill and --writers don't apply then)
+  -b, --batch             don't wait for Enter between the steps
+  -D, --daemon            detach from the terminal (implies --batch)
+  -h, --help";
+
+#[derive (Debug)]
+pub enum Source {
+	Empty,
+	Gen (u32),           // that many random records with ids 0..N
+	Csv (PathBuf),
+	Snapshot (PathBuf)
+}
+
+#[derive (Debug)]
+pub enum Fill {
+	Random,              // 1M..10M rows, as in the original demo
+	Rows (u32)
+}
+
+#[derive (Debug)]
+pub struct Config {
+	pub listen   : SocketAddr,
+	pub data     : Source,
+	pub fill     : Fill,
+	pub writers  : u32,
+	pub ttl      : Option<Duration>,
+	pub snapshot : Option<PathBuf>,
+	pub collation: Collation,
+	pub nulls    : Nulls,
+	pub memory   : Option<usize>,
+	pub seed     : u64,
+	pub primary  : Option<String>,
+	pub batch    : bool,
+	pub daemon   : bool
+}
+
+impl Default for Config {
+	fn default ()->Self {
+		Self {
+			listen   : "127.0.0.1:50001".parse ().unwrap (),
+			data     : Source::Gen (20),
+			fill     : Fill::Random,
+			writers  : 10,
+			ttl      : None,
+			snapshot : None,
+			collation: Collation::Binary,
+			nulls    : Nulls::Last,
+			memory   : None,
+			seed     : rand::random (),
+			primary  : None,
+			batch    : false,
+			daemon   : false
+		}
+	}
+}
+
+impl Config {
+	pub fn from_args (args:&[String])->Result<Self,String> {
+		let mut cfg=Self::default ();
+
+		/* The config file goes first, wherever it is on the command line */
+		let mut opts=Vec::new ();
+		let mut it=args.iter ();
+		while let Some (arg)=it.next () {
+			let key=match arg.as_str () {
+				"-c"|"--config"   => "config",
+				"-l"|"--listen"   => "listen",
+				"-d"|"--data"     => "data",
+				"-f"|"--fill"     => "fill",
+				"-w"|"--writers"  => "writers",
+				"-t"|"--ttl"      => "ttl",
+				"-s"|"--snapshot" => "snapshot",
+				"-C"|"--collation"=> "collation",
+				"-N"|"--nulls"    => "nulls",
+				"-m"|"--memory"   => "memory",
+				"-S"|"--seed"     => "seed",
+				"-P"|"--primary"  => "primary",
+				"-b"|"--batch"    => { opts.push (("batch" , "true")); continue }
+				"-D"|"--daemon"   => { opts.push (("daemon", "true")); continue }
+				_ => return Err (format! ("Unknown option: {arg}"))
+			};
+			let val=it.next ().ok_or (format! ("Missing value for {arg}"))?;
+
+			if key=="config" {
+				cfg.load (val)?;
+			}
+			else {
+				opts.push ((key, val.as_str ()));
+			}
+		}
+
+		for (key,val) in opts {
+			cfg.set (key,val)?;
+		}
+		if cfg.daemon {
+			cfg.batch = true;
+		}
+		if cfg.primary.is_some () {
+			(cfg.data, cfg.fill, cfg.writers) = (Source::Empty, Fill::Rows (0), 0);
+		}
+
+		Ok (cfg)
+	}
+
+	fn load (&mut self, path:&str)->Result<(),String> {
+		let text=fs::read_to_string (path).map_err (|e| format! ("{path}: {e}"))?;
+
+		for (n,line) in text.lines ().enumerate () {
+			let line=line.trim ();
+			if line.is_empty () || line.starts_with ('#') {
+				continue
+			}
+
+			let (key,val) = line.split_once ('=')
+			                .ok_or (format! ("{path}:{}: expected `key = value`", n+1))?;
+			self.set (key.trim (), val.trim ()).map_err (|e| format! ("{path}:{}: {e}", n+1))?;
+		}
+
+		Ok (())
+	}
+
+	fn set (&mut self, key:&str, val:&str)->Result<(),String> {
+		let bad = || format! ("Invalid {key}: {val}");
+
+		match key {
+			"listen"   => self.listen = val.parse ().map_err (|_| bad ())?,
+			"data"     => self.data = match val.split_once (':') {
+				None if val=="empty"       => Source::Empty,
+				Some (("gen"     , n))     => Source::Gen (n.parse ().map_err (|_| bad ())?),
+				Some (("csv"     , p))     => Source::Csv (p.into ()),
+				Some (("snapshot", p))     => Source::Snapshot (p.into ()),
+				_ => return Err (bad ())
+			},
+			"fill"     => self.fill = match val {
+				"random" => Fill::Random,
+				n        => Fill::Rows (n.parse ().map_err (|_| bad ())?)
+			},
+			"writers"  => self.writers = val.parse ().map_err (|_| bad ())?,
+			"ttl"      => self.ttl = Some (Duration::try_from_secs_f64 (val.parse ().map_err (|_| bad ())?).map_err (|_| bad ())?),
+			"snapshot" => self.snapshot = Some (val.into ()),
+			"collation"=> self.collation = match val {
+				"binary"  => Collation::Binary,
+				"nocase"  => Collation::NoCase,
+				"natural" => Collation::Natural,
+				_ => return Err (bad ())
+			},
+			"nulls"    => self.nulls = match val {
+				"first" => Nulls::First,
+				"last"  => Nulls::Last,
+				_       => return Err (bad ())
+			},
+			"memory"   => self.memory = Some (val.parse ().map_err (|_| bad ())?),
+			"seed"     => self.seed = val.parse ().map_err (|_| bad ())?,
+			"primary"  => self.primary = Some (val.to_string ()),
+			"batch"    => self.batch  = val.parse ().map_err (|_| bad ())?,
+			"daemon"   => self.daemon = val.parse ().map_err (|_| bad ())?,
+			_ => return Err (format! ("Unknown option: {key}"))
+		}
+
+		Ok (())
+	}
+}
+
+#[cfg (test)]
+mod tests {
+	use super::*;
+
+	fn args (s:&str)->Vec<String> {
+		s.split_whitespace ().map (String::from).collect ()
+	}
+
+	#[test]
+	fn cli_over_file () {
+		let path=std::env::temp_dir ().join (format! ("srv-cfg-{}", std::process::id ()));
+		fs::write (&path, "# test\nlisten = 0.0.0.0:7000\nwriters = 3\ndata = csv:/tmp/x.csv\ncollation = natural\n").unwrap ();
+
+		let cfg=Config::from_args (&args (&format! ("-w 0 -c {} --daemon", path.display ()))).unwrap ();
+		fs::remove_file (&path).unwrap ();
+
+		assert_eq! (cfg.listen, "0.0.0.0:7000".parse ().unwrap ());
+		assert_eq! (cfg.writers, 0);
+		assert! (matches! (cfg.data, Source::Csv (ref p) if p.to_str ()==Some ("/tmp/x.csv")));
+		assert! (cfg.daemon && cfg.batch && cfg.collation==Collation::Natural);
+
+		assert_eq! (Config::from_args (&args ("-t 1.5")).unwrap ().ttl, Some (Duration::from_millis (1500)));
+		assert_eq! (Config::from_args (&args ("--memory 512")).unwrap ().memory, Some (512));
+		assert_eq! (Config::from_args (&args ("-S 42")).unwrap ().seed, 42);
+
+		let cfg=Config::from_args (&args ("-w 5 --primary 127.0.0.1:7000")).unwrap ();
+		assert! (cfg.writers==0 && matches! (cfg.data, Source::Empty) && cfg.primary.as_deref ()==Some ("127.0.0.1:7000"));
+	}
+
+	#[test]
+	fn bad_args () {
+		assert! (Config::from_args (&args ("--fill lots")).is_err ());
+		assert! (Config::from_args (&args ("--data gen")).is_err ());
+		assert! (Config::from_args (&args ("--listen")).is_err ());
+		assert! (Config::from_args (&args ("--bogus 1")).is_err ());
+		assert! (Config::from_args (&args ("-C icu")).is_err ());
+		assert! (Config::from_args (&args ("-P")).is_err ());
+		assert! (Config::from_args (&args ("--ttl -1")).is_err ());
+	}
+}
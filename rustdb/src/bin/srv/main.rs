@@ -0,0 +1,479 @@
+use core::array;
+use std::{
+	env,
+	fs::File,
+	io::{self,stdout,BufReader,Write},
+	path::Path,
+	process,
+	sync::{
+		atomic::{AtomicBool,AtomicU32,Ordering},
+		mpsc::RecvTimeoutError,
+		Arc
+	},
+	time::{Duration,Instant,SystemTime},
+	thread
+};
+use rand::prelude::*;
+use tokio::{
+	io::{AsyncReadExt,AsyncWriteExt},
+	net::{TcpListener,TcpStream,UdpSocket},
+	runtime::Runtime,
+	signal::unix::{signal,SignalKind},
+	sync::{mpsc,watch},
+	task::{self,JoinSet}
+};
+
+use rustdb::*;
+
+mod config;
+mod replica;
+mod subs;
+use config::*;
+use replica::Replica;
+use subs::*;
+
+const MAX_MSG   :usize    = 64*1024;  // the biggest request accepted, on either transport
+const HEAD_EVERY:Duration = Duration::from_secs (1);  // how often a change stream tells the seq the table is at
+const REAP_EVERY:Duration = Duration::from_secs (1);  // how often the expired rows are removed
+const MIB       :usize    = 1<<20;
+
+fn main () {
+	let args:Vec<String> = env::args ().skip (1).collect ();
+	if args.iter ().any (|a| a=="-h" || a=="--help") {
+		println! ("{USAGE}");
+		return
+	}
+
+	let cfg=Config::from_args (&args).unwrap_or_else (|e| {
+		eprintln! ("{e}\n\n{USAGE}");
+		process::exit (2);
+	});
+
+	// Has to happen before any thread (the runtime's included) is spawned
+	if cfg.daemon && unsafe { libc::daemon (1,0) }!=0 {
+		eprintln! ("Failed to daemonize");
+		process::exit (1);
+	}
+
+	Runtime::new ().unwrap ().block_on (serve (cfg));
+}
+
+// The table of the initial data, the levels of its indexes (and the generated records) drawn from the rng
+fn load (data:&Source, rng:&mut StdRng)->Table {
+	let seed=rng.gen ();
+	let mut t=match data {
+		Source::Empty => {
+			println! ("Step 1: Starting with an empty table\n");
+			Table::with_seed (seed)
+		}
+		Source::Gen (n) => {
+			println! ("Step 1: Adding {n} simple elements to the table to play around\n");
+
+			let mut t=Table::with_seed (seed);
+			for id in 0..*n {
+				t.add (Record::gen (id, rng)).unwrap ();
+			}
+			t
+		}
+		Source::Csv (path) => {
+			println! ("Step 1: Loading {}\n", path.display ());
+			File::open (path).and_then (|f| Table::load_csv (BufReader::new (f)))
+			.unwrap_or_else (|e| fail (path, e))
+		}
+		Source::Snapshot (path) => {
+			println! ("Step 1: Restoring {}\n", path.display ());
+			Table::load_from (path).unwrap_or_else (|e| fail (path, e))
+		}
+	};
+	if matches! (data, Source::Csv (_) | Source::Snapshot (_)) {
+		t.reseed (seed);  // for the rows from now on, those loaded are as they've come
+	}
+
+	fn fail (path:&Path, e:io::Error)->! {
+		eprintln! ("{}: {e}", path.display ());
+		process::exit (1);
+	}
+
+	t
+}
+
+// Removes the expired rows of all the tables, every REAP_EVERY, taking the write lock only when there are some
+fn reap (db:&Database) {
+	loop {
+		thread::sleep (REAP_EVERY);
+		for (_,name,_) in db.list () {
+			let Some (t)=db.get (&name) else { continue };
+			if t.read ().unwrap ().next_expiry ().is_some_and (|at| at<=SystemTime::now ()) {
+				t.write ().unwrap ().reap ();
+			}
+		}
+	}
+}
+
+async fn serve (cfg:Config) {
+	println! ("Seed {}\n", cfg.seed);
+	let mut rng=StdRng::seed_from_u64 (cfg.seed);  // every random choice comes from it, but the writers' interleaving
+	let mut t=load (&cfg.data, &mut rng);
+	if cfg.collation!=Collation::Binary {
+		t.set_collation (Column::Str, cfg.collation).unwrap ();
+	}
+	if cfg.nulls!=Nulls::Last {
+		for col in [Column::Num, Column::Str] {
+			t.set_nulls (col, cfg.nulls).unwrap ();
+		}
+	}
+	t.set_memory_cap (cfg.memory.map (|m| m*MIB));
+	let db=Arc::new (Database::with_seed (rng.gen ()));
+	db.attach (DEFAULT_TABLE, t).unwrap ();
+	let t=db.get (DEFAULT_TABLE).unwrap ();  // the one the writers and the snapshot are about
+
+	let replica=cfg.primary.as_deref ().map (|addr| Arc::new (Replica::new (addr)));
+	if let Some (r)=&replica {
+		println! ("Replicating {DEFAULT_TABLE} from {}\n", cfg.primary.as_ref ().unwrap ());
+		db.set_read_only (true);
+		let (r,t) = (Arc::clone (r), Arc::clone (&t));
+		thread::spawn (move || r.run (&t));
+	}
+	else {
+		let db=Arc::clone (&db);
+		thread::spawn (move || reap (&db));
+	}
+	let stop=Arc::new (AtomicBool::new (false));  // tells the emulated writers to quit
+
+	let pause = move || if !cfg.batch {
+		println! ("Press Enter to continue");
+		unsafe {libc::getchar ()};
+	};
+
+	let (tt,stp,fill,n,ttl)=(Arc::clone (&t), Arc::clone (&stop), cfg.fill, cfg.writers, cfg.ttl);
+	thread::spawn (move || {
+		let num=match fill {
+			Fill::Random    => rng.gen_range (1e6..10e6) as u32,
+			Fill::Rows (n)  => n
+		};
+		if num>0 {
+			pause ();
+			println! ("Step 2: Filling the table with {num} elements...");
+
+			// Continue after the highest id, the initial data isn't necessarily dense
+			let cur=tt.read ().unwrap ().iter ().map (|r| r.id+1).max ().unwrap_or (0);
+			for i in 0..num {
+				if stp.load (Ordering::SeqCst) {
+					return
+				}
+				if i%10000==0 {
+					print! ("\ri={i}\x1B[K");
+					stdout ().flush ().unwrap ();
+				}
+				if let Err (e)=tt.write ().unwrap ().add (Record::gen (i+cur, &mut rng)) {
+					println! ("\rStopped at i={i}: {e}");
+					break
+				}
+			}
+			let t=tt.read ().unwrap ();
+			println! ("\rtot={}, memory={:.1} MiB", t.tot (), t.memory_usage ().total () as f64/MIB as f64);
+		}
+
+		if n==0 {
+			return
+		}
+		pause ();
+
+		println! ("Step 3: Creating {n} artificial writers that will randomly add/rm/upd...");
+		let c:[AtomicU32;3] = array::from_fn (|_| AtomicU32::new (0));
+		let c=Arc::new (c);
+
+		for i in 0..n {
+			let (c,tt,stp) = (Arc::clone (&c), Arc::clone (&tt), Arc::clone (&stp));
+			let mut rng=StdRng::seed_from_u64 (rng.gen ());
+			thread::spawn (move || {
+				let freq = rng.gen_range (3..=100);
+				let st   = (1e6/freq as f64).round () as u64;
+				println! ("Thread {i}: freq={freq}, st={st}");
+
+				while !stp.load (Ordering::SeqCst) {
+					let op=rng.gen_range (0..=2) as usize;
+					let id=rng.gen_range (0..10e6 as u32);
+					let res = match op {
+						0 => match ttl {
+							None      => tt.write ().unwrap ().add (Record::gen (id, &mut rng)),
+							Some (ttl) => tt.write ().unwrap ().add_expiring (Record::gen (id, &mut rng), ttl)
+						},
+						1 => tt.write ().unwrap ().rm (id),
+						2 => tt.write ().unwrap ().upd (Record::gen (id, &mut rng)),
+						_=> unreachable! ()
+					};
+
+					if res.is_ok () {
+						c[op].fetch_add (1, Ordering::SeqCst);
+					}
+
+					thread::sleep (Duration::from_micros (st));
+				}
+			});
+		}
+
+		while !stp.load (Ordering::SeqCst) {
+			thread::sleep (Duration::from_secs (1));
+
+			print! ("\ra={}/s, r={}/s, u={}/s, tot={}\x1B[K",
+			        c[0].load (Ordering::SeqCst),
+			        c[1].load (Ordering::SeqCst),
+			        c[2].load (Ordering::SeqCst),
+			        tt.read ().unwrap ().tot ());
+			stdout ().flush ().unwrap ();
+
+			c.iter ().for_each (|v| {
+				v.store (0, Ordering::SeqCst)
+			});
+		}
+	});
+
+	let socket=UdpSocket::bind (cfg.listen).await.unwrap_or_else (|e| {
+		eprintln! ("{}: {e}", cfg.listen);
+		process::exit (1);
+	});
+	let socket=Arc::new (socket);
+	let listener=TcpListener::bind (cfg.listen).await.unwrap_or_else (|e| {
+		eprintln! ("{}: {e}", cfg.listen);
+		process::exit (1);
+	});
+
+	let subs=Arc::new (Subs::default ());
+	tokio::spawn (Arc::clone (&subs).run (Arc::clone (&db), Arc::clone (&socket)));
+
+	let mut sigint =signal (SignalKind::interrupt ()).unwrap ();
+	let mut sigterm=signal (SignalKind::terminate ()).unwrap ();
+	let (quit,quit_rx)=watch::channel (false);  // tells the TCP sessions to hang up
+	let mut inflight=JoinSet::new ();
+	let mut sid=0;  // TCP session ids
+
+	loop {
+		let mut buf=[0;MAX_MSG];
+
+		tokio::select! {
+			res = socket.recv_from (&mut buf) => {
+				// e.g. ECONNREFUSED, for a page pushed to a client that has gone since
+				let (amt,src) = match res {
+					Ok (r)  => r,
+					Err (e) => { eprintln! ("\nFailed to receive a request: {e}"); continue }
+				};
+				let msg=buf[..amt].to_vec ();
+
+				let (db,subs,socket,replica) = (Arc::clone (&db), Arc::clone (&subs), Arc::clone (&socket), replica.clone ());
+				inflight.spawn (async move {
+					let Some (data)=reply (&db, &subs, replica.as_deref (), Peer::Udp (src), Dest::Udp (src), &msg).await else {
+						eprintln! ("\nMalformed request from {src}");
+						return
+					};
+
+					if let Err (e)=socket.send_to (&data, src).await {
+						eprintln! ("\nFailed to reply to {src}: {e}");
+					}
+				});
+			}
+
+			res = listener.accept () => {
+				match res {
+					Ok ((sock,_)) => {
+						sid += 1;
+						inflight.spawn (session (Arc::clone (&db), Arc::clone (&subs), replica.clone (), sid, sock, quit_rx.clone ()));
+					}
+					Err (e) => eprintln! ("\nFailed to accept a connection: {e}")
+				}
+			}
+
+			Some (_) = inflight.join_next (), if !inflight.is_empty () => {}
+
+			_ = sigint .recv () => break,
+			_ = sigterm.recv () => break
+		}
+	}
+
+	/* Graceful shutdown: answer the requests already taken, stop the writers, then flush */
+	println! ("\nShutting down...");
+	quit.send (true).unwrap ();
+	while inflight.join_next ().await.is_some () {}
+	stop.store (true, Ordering::SeqCst);
+
+	if let Some (path)=cfg.snapshot {
+		let res=task::spawn_blocking (move || t.read ().unwrap ().save_to (&path)).await.unwrap ();
+		match res {
+			Ok (_)  => println! ("Snapshot saved"),
+			Err (e) => eprintln! ("Failed to save the snapshot: {e}")
+		}
+	}
+}
+
+// Both transports end up here: the lock (of the table asked for) is only held to collect the records,
+// the encoding runs on the blocking pool as well
+async fn reply (db:&Arc<Database>, subs:&Arc<Subs>, replica:Option<&Replica>, peer:Peer, to:Dest, buf:&[u8])->Option<Vec<u8>> {
+	// A query in the text form, which isn't subscribed to
+	if let Some ((tag,text))=Query::from_msg (buf) {
+		if let (Some (r), Ok (Statement::Replication)) = (replica, Statement::parse (text)) {
+			return Some (r.answer (tag))
+		}
+		let (db,text)=(Arc::clone (db), text.to_string ());
+		return Some (task::spawn_blocking (move || db.answer (tag, &text)).await.unwrap ())
+	}
+
+	// Changes are streamed over TCP only
+	if let Some (req)=ChangesReq::from_bytes (buf) {
+		return Some (refused (req.tag))
+	}
+
+	// A histogram: not subscribed to either, and the client has left the page it was watching
+	if let Some (req)=GroupReq::from_bytes (buf) {
+		subs.remove (peer);
+		let Some (t)=db.by_id (req.tbl) else {
+			return Some (refused (req.tag))
+		};
+		return Some (task::spawn_blocking (move || t.read ().unwrap ().group_answer (req)).await.unwrap ())
+	}
+
+	// The request, optionally followed by a filter
+	let (req,filter) = buf.split_at_checked (REQ_SZ)?;
+	let req=Request::from_bytes (req)?;
+	let filter=if filter.is_empty () {None} else {Some (Arc::new (Filter::from_bytes (filter)?))};
+	let Some (t)=db.by_id (req.tbl) else {
+		subs.remove (peer);
+		return Some (refused (req.tag))
+	};
+
+	let subs=Arc::clone (subs);
+	let data=task::spawn_blocking (move || {
+		let (resp,recs)=subs.serve (&t.read ().unwrap (), peer, to, req, filter);
+		resp.encode (&recs)
+	}).await.unwrap ();
+
+	Some (data)
+}
+
+/* Streams the changes of the table to the session: the events are forwarded by a blocking task
+   (the table's channel being a blocking one) for as long as the session lasts, with the head every second */
+fn follow (db:&Database, req:ChangesReq, tx:&mpsc::UnboundedSender<Vec<u8>>) {
+	let Some (t)=db.by_id (req.tbl) else {
+		let _=tx.send (refused (req.tag));
+		return
+	};
+	let (stream,res) = {
+		let mut t=t.write ().unwrap ();
+		let stream=t.stream ();
+		(stream, match req.full {
+			true                                         => Ok (t.changes_full ()),
+			false if req.stream!=0 && req.stream!=stream => Err (format! ("Can't resume from {}, that's of another stream", {req.from})),
+			false => t.changes (req.from).map (|rx| (0,vec![],rx)).map_err (|seq| format! ("Can't resume from {}, the nearest is {seq}", {req.from}))
+		})
+	};
+
+	let (seq,rows,rx) = match res {
+		Ok (res)  => res,
+		Err (why) => {
+			let mut data=refused (req.tag);
+			data.extend_from_slice (why.as_bytes ());
+			let _=tx.send (data);
+			return
+		}
+	};
+	let _=tx.send (Response { ok: true, CS: 0, NS: 0, tag: req.tag }.encode (&[]));
+
+	// Weak references: not to keep the writer from quitting once the session is over, nor the table once dropped
+	let (tx,t) = (tx.downgrade (), Arc::downgrade (&t));
+	task::spawn_blocking (move || {
+		if req.full {  // the rows, encoded here as well, there may be lots of them
+			let Some (tx)=tx.upgrade () else {
+				return
+			};
+			for ev in rows {
+				let _=tx.send (ev.encode ());
+			}
+			let _=tx.send (Event::head (stream, seq));
+		}
+
+		let mut head=Instant::now ();
+		loop {
+			let msg=match rx.recv_timeout (HEAD_EVERY) {
+				Ok (ev) => ev.encode (),
+				Err (RecvTimeoutError::Timeout) => vec![],
+				Err (_) => break  // the table is dropped
+			};
+			let Some (tx)=tx.upgrade () else {
+				break  // the session is over
+			};
+			if !msg.is_empty () && tx.send (msg).is_err () {
+				break
+			}
+			if head.elapsed ()>=HEAD_EVERY {
+				let Some (t)=t.upgrade () else {
+					break
+				};
+				let seq=t.read ().unwrap ().seq ();
+				if tx.send (Event::head (stream, seq)).is_err () {
+					break
+				}
+				head = Instant::now ();
+			}
+		}
+	});
+}
+
+// The answer to a request for a table there's no such of
+fn refused (tag:u32)->Vec<u8> {
+	Response { ok: false, CS: 0, NS: 0, tag }.encode (&[])
+}
+
+/* A TCP client: length-prefixed requests and responses until either side hangs up.
+   Replies and pushed pages share the writer task. */
+async fn session (db:Arc<Database>, subs:Arc<Subs>, replica:Option<Arc<Replica>>, sid:u64, sock:TcpStream, mut quit:watch::Receiver<bool>) {
+	let peer=sock.peer_addr ().map (|a| a.to_string ()).unwrap_or_default ();
+	let (mut rd, mut wr) = sock.into_split ();
+
+	let (tx,mut rx) = mpsc::unbounded_channel::<Vec<u8>> ();
+	let writer=tokio::spawn (async move {
+		while let Some (data)=rx.recv ().await {
+			let hdr=(data.len () as u32).to_ne_bytes ();
+			if wr.write_all (&hdr).await.is_err () || wr.write_all (&data).await.is_err () {
+				break
+			}
+		}
+	});
+
+	loop {
+		let mut hdr=[0u8;FRAME_HDR];
+		tokio::select! {
+			res = rd.read_exact (&mut hdr) => if res.is_err () {
+				break
+			},
+			_ = quit.changed () => break
+		}
+
+		let len=u32::from_ne_bytes (hdr) as usize;
+		if len>MAX_MSG {
+			eprintln! ("\nOversized request from {peer}");
+			break
+		}
+		let mut buf=vec![0;len];
+		if rd.read_exact (&mut buf).await.is_err () {
+			break
+		}
+
+		// A change stream: the answer and then the events go out through the writer
+		if let Some (req)=ChangesReq::from_bytes (&buf) {
+			follow (&db, req, &tx);
+			continue
+		}
+
+		let Some (data)=reply (&db, &subs, replica.as_deref (), Peer::Tcp (sid), Dest::Tcp (tx.clone ()), &buf).await else {
+			eprintln! ("\nMalformed request from {peer}");
+			break
+		};
+		if tx.send (data).is_err () {
+			break
+		}
+	}
+
+	// Once nothing else holds the sender, the writer flushes what's left and quits
+	subs.remove (Peer::Tcp (sid));
+	drop (tx);
+	let _=writer.await;
+}
@@ -0,0 +1,163 @@
+/* Replica mode: the default table follows that of the primary through its change stream (over TCP),
+   the database being read-only otherwise. It starts with a full copy (the rows, then the changes after them),
+   and on a reconnect resumes after the last change applied, unless the primary no longer has it
+   or its stream is another one (it has restarted, say, and counts its seqs from 1 again): then it copies
+   the table all over again.
+   The primary tells the seq it's at every second, that's what the lag is counted against.
+   A replica that gets to its memory cap stops there, copying it all again would only get to it again:
+   SHOW REPLICATION tells so. */
+
+use std::{
+	io::{self,Read,Write},
+	net::TcpStream,
+	sync::{
+		atomic::{AtomicU64,Ordering},
+		Mutex,RwLock
+	},
+	thread,
+	time::{Duration,Instant}
+};
+
+use rustdb::*;
+
+const RETRY:Duration = Duration::from_secs (1);
+const PRIMARY_TABLE:u32 = 0;  // the primary's default table, the first one it makes
+const MAX_EVENT:usize = 64;   // longest event message
+
+#[derive (Debug)]
+pub struct Replica {
+	primary: String,
+	stream : AtomicU64,             // id of the primary's stream the seqs are of
+	applied: AtomicU64,             // seq of the primary's last change applied here
+	head   : AtomicU64,             // the primary's latest seq, as last heard
+	heard  : Mutex<Option<Instant>>, // when the primary was last heard from
+	stopped: Mutex<Option<String>>   // why it no longer follows
+}
+
+impl Replica {
+	pub fn new (primary:&str)->Self {
+		Replica {
+			primary: primary.to_string (),
+			stream : AtomicU64::new (0),
+			applied: AtomicU64::new (0),
+			head   : AtomicU64::new (0),
+			heard  : Mutex::new (None),
+			stopped: Mutex::new (None)
+		}
+	}
+
+	pub fn status (&self)->String {
+		let (applied,head) = (self.applied.load (Ordering::SeqCst), self.head.load (Ordering::SeqCst));
+		if let Some (why)=&*self.stopped.lock ().unwrap () {
+			return format! ("primary {}: stopped at seq {applied} of {head}: {why}\n", self.primary)
+		}
+		match *self.heard.lock ().unwrap () {
+			None    => format! ("primary {}: connecting\n", self.primary),
+			Some (t) => format! ("primary {}: seq {applied} of {head}, {} behind, heard {:.1}s ago\n",
+			                     self.primary, head.saturating_sub (applied), t.elapsed ().as_secs_f32 ())
+		}
+	}
+
+	// The answer to SHOW REPLICATION
+	pub fn answer (&self, tag:u32)->Vec<u8> {
+		let mut data=Response { ok: true, CS: 0, NS: 0, tag }.encode (&[]);
+		data.extend_from_slice (self.status ().as_bytes ());
+		data
+	}
+
+	// Keeps the table following the primary, reconnecting whenever the connection breaks
+	pub fn run (&self, t:&RwLock<Table>) {
+		let mut full=true;  // whether the next connection starts over with a full copy
+		loop {
+			if let Err (e)=self.follow (t, &mut full) {
+				eprintln! ("\nReplication from {}: {e}", self.primary);
+			}
+			if self.stopped.lock ().unwrap ().is_some () {
+				return
+			}
+			thread::sleep (RETRY);
+		}
+	}
+
+	// A connection to the primary, for as long as it lasts
+	fn follow (&self, t:&RwLock<Table>, full:&mut bool)->io::Result<()> {
+		let mut s=TcpStream::connect (&self.primary)?;
+		s.set_nodelay (true)?;
+
+		let req=ChangesReq::new (PRIMARY_TABLE, self.stream.load (Ordering::SeqCst), self.applied.load (Ordering::SeqCst), *full, 1);
+		send (&mut s, &req.to_bytes ())?;
+		let buf=recv (&mut s, RESP_SZ+1024)?;
+		if !Response::from_bytes (&buf).ok_or (io::ErrorKind::InvalidData)?.ok {
+			*full = true;  // can't resume: next time over again
+			return Err (io::Error::other (String::from_utf8_lossy (&buf[RESP_SZ..]).to_string ()))
+		}
+
+		if *full {
+			println! ("\nCopying the table from {}", self.primary);
+			t.write ().unwrap ().delete_range (Column::Id, ..);
+		}
+		loop {
+			let buf=recv (&mut s, MAX_EVENT)?;
+			*self.heard.lock ().unwrap () = Some (Instant::now ());
+
+			// the rows of a full copy are all there once the head comes
+			if let Some ((stream,seq))=Event::head_of (&buf) {
+				if *full {
+					*full = false;
+					self.stream.store (stream, Ordering::SeqCst);
+					self.applied.store (seq, Ordering::SeqCst);
+				}
+				else if stream!=self.stream.load (Ordering::SeqCst) {
+					*full = true;
+					return Err (io::Error::other ("the primary's stream is another one"))
+				}
+				self.head.store (seq, Ordering::SeqCst);
+				continue
+			}
+
+			// a change that doesn't apply means the table is no longer the primary's: it's copied over again,
+			// unless it's the table that is full
+			let ev=Event::decode (&buf).ok_or (io::ErrorKind::InvalidData)?;
+			let mut t=t.write ().unwrap ();
+			let res=match ev.change {
+				Change::Insert (r)       => t.add (*r),
+				Change::Delete (r)       => t.rm (r.id),
+				Change::Update {after,..} => t.upd (*after),
+				Change::Expiry {id,at}    => t.set_expiry (id, at)
+			};
+			drop (t);
+			if let Err (e@Error::Full {..})=res {
+				*self.stopped.lock ().unwrap () = Some (e.to_string ());
+				return Err (io::Error::other (format! ("change {} doesn't fit ({e}), stopped", ev.seq)))
+			}
+			if let Err (e)=res {
+				*full = true;
+				return Err (io::Error::other (format! ("change {} doesn't apply ({e}), the table is to be copied again", ev.seq)))
+			}
+
+			if !*full {
+				self.applied.store (ev.seq, Ordering::SeqCst);
+				self.head.fetch_max (ev.seq, Ordering::SeqCst);
+			}
+		}
+	}
+}
+
+// Length-prefixed, as everything over TCP
+fn send (s:&mut TcpStream, msg:&[u8])->io::Result<()> {
+	s.write_all (&(msg.len () as u32).to_ne_bytes ())?;
+	s.write_all (msg)
+}
+
+fn recv (s:&mut TcpStream, max:usize)->io::Result<Vec<u8>> {
+	let mut hdr=[0u8;FRAME_HDR];
+	s.read_exact (&mut hdr)?;
+	let len=u32::from_ne_bytes (hdr) as usize;
+	if len>max {
+		return Err (io::Error::new (io::ErrorKind::InvalidData, "oversized message"))
+	}
+
+	let mut buf=vec![0;len];
+	s.read_exact (&mut buf)?;
+	Ok (buf)
+}
@@ -0,0 +1,147 @@
+/* Live pages: a client that sets Request::sub gets its page re-sent whenever its table changes.
+   The page is kept at the key of the record that was on top of it, so the view doesn't drift
+   while the writers add and remove rows around it. Once the table is dropped, so is the subscription.
+   A row expiring changes the page as well, at once, rather than once the reaper has been. */
+
+use std::{
+	collections::{BTreeMap,HashMap},
+	net::SocketAddr,
+	sync::{Arc,Mutex},
+	time::{Duration,Instant,SystemTime}
+};
+use tokio::{
+	net::UdpSocket,
+	sync::mpsc::UnboundedSender,
+	task,
+	time
+};
+
+use rustdb::*;
+
+const TICK   :Duration = Duration::from_millis (100);  // changes are coalesced over that long
+const UDP_TTL:Duration = Duration::from_secs (30);     // a UDP client has to renew its subscription
+
+#[derive (Debug, Copy,Clone, PartialEq,Eq, Hash)]
+pub enum Peer {
+	Udp (SocketAddr),
+	Tcp (u64)          // session id
+}
+
+#[derive (Debug, Clone)]
+pub enum Dest {
+	Udp (SocketAddr),
+	Tcp (UnboundedSender<Vec<u8>>)  // the session writes it out
+}
+
+#[derive (Debug, Clone)]
+struct Sub {
+	to    : Dest,
+	req   : Request,             // to re-fetch the page with
+	filter: Option<Arc<Filter>>,
+	ver   : u64,                 // of the table, as of the page sent last
+	until : Option<SystemTime>,  // when a row of the table expires next, as of then
+	seen  : Instant
+}
+
+#[derive (Debug, Default)]
+pub struct Subs (Mutex<HashMap<Peer,Sub>>);
+
+impl Subs {
+	// Serves every request for a page off its table: (re)subscribes the peer, or drops it if it doesn't want updates
+	pub fn serve (&self, t:&Table, peer:Peer, to:Dest, req:Request, filter:Option<Arc<Filter>>)->(Response,Vec<Arc<Record>>) {
+		let (resp,recs)=t.fetch_filtered (req, filter.as_deref ());
+		let mut subs=self.0.lock ().unwrap ();
+
+		if !req.sub || !resp.ok {
+			subs.remove (&peer);
+		}
+		else {
+			subs.insert (peer, Sub {
+				to,
+				req   : at (req, &resp, &recs),
+				filter,
+				ver   : t.ver (),
+				until : t.next_change (),
+				seen  : Instant::now ()
+			});
+		}
+
+		(resp,recs)
+	}
+
+	pub fn remove (&self, peer:Peer) {
+		self.0.lock ().unwrap ().remove (&peer);
+	}
+
+	/* Watch the tables' versions and push the fresh pages out */
+	pub async fn run (self:Arc<Self>, db:Arc<Database>, socket:Arc<UdpSocket>) {
+		let mut tick=time::interval (TICK);
+
+		loop {
+			tick.tick ().await;
+
+			let (subs,db) = (Arc::clone (&self), Arc::clone (&db));
+			let out=task::spawn_blocking (move || subs.refresh (&db)).await.unwrap ();
+
+			for (to,data) in out {
+				match to {
+					Dest::Udp (addr) => { let _=socket.send_to (&data, addr).await; }
+					Dest::Tcp (tx)   => { let _=tx.send (data); }
+				}
+			}
+		}
+	}
+
+	// The pages to push: of those subscribed to the tables that have changed since
+	fn refresh (&self, db:&Database)->Vec<(Dest,Vec<u8>)> {
+		let mut tables:BTreeMap<u32,Vec<(Peer,Sub)>> = BTreeMap::new ();
+		{
+			let mut subs=self.0.lock ().unwrap ();
+			subs.retain (|p,s| !matches! (p, Peer::Udp (_)) || s.seen.elapsed ()<UDP_TTL);
+			for (p,s) in subs.iter () {
+				tables.entry (s.req.tbl).or_default ().push ((*p,s.clone ()));
+			}
+		}
+
+		// Each table is locked in turn, for its own subscribers only
+		let (mut pages, mut gone) = (vec![], vec![]);
+		for (tbl,subs) in tables {
+			let Some (t)=db.by_id (tbl) else {
+				gone.extend (subs.into_iter ().map (|(p,_)| p));
+				continue
+			};
+			let t=t.read ().unwrap ();
+			let now=SystemTime::now ();
+			for (peer,sub) in subs.into_iter ().filter (|(_,s)| s.ver!=t.ver () || s.until.is_some_and (|u| u<=now)) {
+				let (resp,recs)=t.fetch_filtered (sub.req, sub.filter.as_deref ());
+				pages.push ((peer,sub,t.ver (),t.next_change (),resp,recs));
+			}
+		}
+
+		/* Encoding is done outside of the table locks */
+		let mut out=vec![];
+		let mut subs=self.0.lock ().unwrap ();
+		for peer in gone {
+			subs.remove (&peer);
+		}
+		for (peer,sub,ver,until,resp,recs) in pages {
+			out.push ((sub.to.clone (), resp.encode (&recs)));
+
+			// unless the client has sent something else meanwhile
+			if let Some (s)=subs.get_mut (&peer).filter (|s| s.req.tag==sub.req.tag) {
+				(s.req, s.ver, s.until) = (at (sub.req, &resp, &recs), ver, until);
+			}
+		}
+
+		out
+	}
+}
+
+/* The request for the page as served, from its top record on. The key stays valid even once
+   the record is gone: the page then starts with whatever follows it. */
+fn at (req:Request, resp:&Response, recs:&[Arc<Record>])->Request {
+	match recs.first () {
+		Some (first) => Request { cur: Cursor::At, key: **first, ..req },
+		None         => Request { CS: resp.CS, NS: resp.NS, ..req }
+	}
+}
@@ -0,0 +1,289 @@
+/* Change data capture: every change of a table as an event, numbered in the order of the changes.
+	The inserts and the deletes carry the record, the updates the record before and after,
+	the expiries the id and the time (the rows go on their own once expired, with the reaper's deletes).
+	A subscriber gets the events on a channel, from any point the table still keeps them from:
+	the latest KEEP of them are, for the subscribers that have been away to catch up.
+	The channels are unbounded, it's for the subscriber to keep up.
+	A full stream starts with the rows there are, as inserts numbered with the seq they're as of, and their expiries.
+	The seqs start over with a table made anew (a server restarted, say), so each stream has an id of its own,
+	drawn at random: a seq is only good for resuming the stream it came from.
+	On the wire (TCP only) a ChangesReq goes instead of a Request, and is answered with the response header,
+	not ok if the point is too far back (with the nearest one possible in the text), then the events follow,
+	each a message of its own: the CHANGES byte, the kind, the seq, the record(s) (or the id and the time in ms,
+	0 - none). Every now and then
+	(and right after the rows of a full stream) there's the head instead: the stream's id and the seq the table is at.
+ */
+
+use rand::Rng;
+use std::{
+	collections::VecDeque,
+	fmt,
+	ptr,
+	sync::mpsc::{self,Receiver,Sender},
+	time::{Duration,SystemTime,UNIX_EPOCH}
+};
+
+use crate::*;
+
+pub const CHANGES:u8    = 0xFD;     // the first byte of a ChangesReq and of an event, never that of a Request
+const KEEP       :usize = 1<<16;    // events kept to resume from
+const HEAD       :u8    = 3;        // the kind of the head message
+const EXPIRY     :u8    = 4;        // that of an expiry
+
+#[derive (Debug, Clone, PartialEq)]
+pub enum Change {
+	Insert (Arc<Record>),
+	Delete (Arc<Record>),
+	Update {before: Arc<Record>, after: Arc<Record>},
+	Expiry {id: u32, at: Option<SystemTime>}  // None - cleared
+}
+
+#[derive (Debug, Clone, PartialEq)]
+pub struct Event {
+	pub seq   : u64,  // 1 for the first change of the table, and so on without gaps
+	pub change: Change
+}
+
+#[derive (Debug)]
+pub(crate) struct Changes {
+	id  : u64,                // of the stream, never 0
+	seq : u64,                // of the latest event
+	keep: VecDeque<Event>,    // the latest events
+	subs: Vec<Sender<Event>>
+}
+
+impl Changes {
+	// Not from the table's seed: a table made anew from the same one has another stream all the same
+	pub fn new ()->Self {
+		Changes { id: rand::thread_rng ().gen_range (1..=u64::MAX), seq: 0, keep: VecDeque::new (), subs: vec![] }
+	}
+
+	pub fn publish (&mut self, change:Change) {
+		self.seq += 1;
+		let ev=Event { seq: self.seq, change };
+
+		// a subscriber that has hung up is dropped
+		self.subs.retain (|tx| tx.send (ev.clone ()).is_ok ());
+		if self.keep.len ()==KEEP {
+			self.keep.pop_front ();
+		}
+		self.keep.push_back (ev);
+	}
+}
+
+impl Table {
+	/* The events after the seq (0 - from the first one), those kept ones first, then as they come.
+	   The error is the nearest seq possible: the earliest if those after it are no longer kept, the latest if it's ahead. */
+	pub fn changes (&mut self, from:u64)->Result<Receiver<Event>,u64> {
+		let c=&mut self.cdc;
+		let first=c.keep.front ().map_or (c.seq, |e| e.seq-1);
+		if from<first || from>c.seq {
+			return Err (from.clamp (first, c.seq))
+		}
+
+		let (tx,rx) = mpsc::channel ();
+		for ev in c.keep.iter ().skip ((from-first) as usize) {
+			tx.send (ev.clone ()).unwrap ();
+		}
+		c.subs.push (tx);
+
+		Ok (rx)
+	}
+
+	// The seq the table is at, the rows there are (as inserts of that seq, then their expiries), and the events after it as they come
+	pub fn changes_full (&mut self)->(u64,Vec<Event>,Receiver<Event>) {
+		let seq=self.cdc.seq;
+		let mut rows:Vec<Event> = self.fields[Column::Id as usize].iter ().map (|r| Event { seq, change: Change::Insert (r.clone ()) }).collect ();
+		rows.extend (self.ttl.iter ().map (|(id,_)| Event { seq, change: Change::Expiry { id, at: self.expiry (id) } }));
+
+		(seq, rows, self.changes (seq).unwrap ())
+	}
+
+	// The seq of the latest change
+	pub fn seq (&self)->u64 {
+		self.cdc.seq
+	}
+
+	// The id of the stream the seqs are of
+	pub fn stream (&self)->u64 {
+		self.cdc.id
+	}
+}
+
+#[derive (Debug, Copy,Clone)]
+#[repr (C,packed)]
+pub struct ChangesReq {
+	pub kind  : u8  ,  // CHANGES
+	pub full  : bool,  // the rows first, stream and from are ignored then
+	pub tbl   : u32 ,
+	pub stream: u64 ,  // the id of the stream from is of, 0 - whichever the table has
+	pub from  : u64 ,  // the seq the subscriber has got up to
+	pub tag   : u32
+}
+pub const CREQ_SZ:usize = mem::size_of::<ChangesReq> ();
+
+impl ChangesReq {
+	pub fn new (tbl:u32, stream:u64, from:u64, full:bool, tag:u32)->Self {
+		ChangesReq { kind: CHANGES, full, tbl, stream, from, tag }
+	}
+
+	pub fn to_bytes (&self)->Vec<u8> {
+		let p=self as *const ChangesReq as *const u8;
+		unsafe { slice::from_raw_parts (p,CREQ_SZ) }.to_vec ()
+	}
+
+	pub fn from_bytes (buf:&[u8])->Option<Self> {
+		if buf.len ()!=CREQ_SZ || buf[0]!=CHANGES || buf[1]>1 {
+			return None
+		}
+		Some (unsafe { ptr::read_unaligned (buf.as_ptr () as *const ChangesReq) })
+	}
+}
+
+impl Event {
+	pub fn encode (&self)->Vec<u8> {
+		let (kind,recs) = match &self.change {
+			Change::Insert (r)            => (0u8, vec![r]),
+			Change::Delete (r)            => (1  , vec![r]),
+			Change::Update {before,after} => (2  , vec![before,after]),
+			Change::Expiry {..}           => (EXPIRY, vec![])
+		};
+		let mut data=vec![CHANGES, kind];
+		data.extend_from_slice (&self.seq.to_ne_bytes ());
+		if let Change::Expiry {id,at} = self.change {
+			let ms=at.map_or (0, |at| at.duration_since (UNIX_EPOCH).map_or (0, |d| d.as_millis () as u64));
+			data.extend_from_slice (&id.to_ne_bytes ());
+			data.extend_from_slice (&ms.to_ne_bytes ());
+		}
+		for r in recs {
+			let p=&**r as *const Record as *const u8;
+			data.extend_from_slice (unsafe { slice::from_raw_parts (p,REC_SZ) });
+		}
+		data
+	}
+
+	pub fn decode (buf:&[u8])->Option<Self> {
+		const HDR:usize = 2 + mem::size_of::<u64> ();
+		if buf.len ()<HDR || buf[0]!=CHANGES {
+			return None
+		}
+		let seq=u64::from_ne_bytes (buf[2..HDR].try_into ().unwrap ());
+		if buf[1]==EXPIRY {
+			let (id,ms) = buf[HDR..].split_at_checked (4)?;
+			let (id,ms) = (u32::from_ne_bytes (id.try_into ().ok ()?), u64::from_ne_bytes (ms.try_into ().ok ()?));
+			let at=(ms>0).then (|| UNIX_EPOCH + Duration::from_millis (ms));
+			return Some (Event { seq, change: Change::Expiry { id, at } })
+		}
+		let recs:Vec<Arc<Record>> = buf[HDR..].chunks (REC_SZ).map (|r| match r.len () {
+			REC_SZ => Some (Arc::new (unsafe { ptr::read_unaligned (r.as_ptr () as *const Record) })),
+			_      => None
+		}).collect::<Option<_>> ()?;
+
+		let change=match (buf[1], &recs[..]) {
+			(0, [r])            => Change::Insert (r.clone ()),
+			(1, [r])            => Change::Delete (r.clone ()),
+			(2, [before,after]) => Change::Update { before: before.clone (), after: after.clone () },
+			_                   => return None
+		};
+		Some (Event { seq, change })
+	}
+
+	// The head message: the id of the stream and the seq the table is at
+	pub fn head (stream:u64, seq:u64)->Vec<u8> {
+		let mut data=vec![CHANGES, HEAD];
+		data.extend_from_slice (&stream.to_ne_bytes ());
+		data.extend_from_slice (&seq.to_ne_bytes ());
+		data
+	}
+
+	pub fn head_of (buf:&[u8])->Option<(u64,u64)> {
+		match buf {
+			[CHANGES, HEAD, rest@..] if rest.len ()==16 => {
+				let (stream,seq) = rest.split_at (8);
+				Some ((u64::from_ne_bytes (stream.try_into ().ok ()?), u64::from_ne_bytes (seq.try_into ().ok ()?)))
+			}
+			_ => None
+		}
+	}
+}
+
+impl fmt::Display for Event {
+	fn fmt (&self, f:&mut fmt::Formatter<'_>)->fmt::Result {
+		match &self.change {
+			Change::Insert (r)            => write! (f, "{} insert {r}", self.seq),
+			Change::Delete (r)            => write! (f, "{} delete {r}", self.seq),
+			Change::Update {before,after} => write! (f, "{} update {before} -> {after}", self.seq),
+			Change::Expiry {id,at}        => match at.and_then (|at| at.duration_since (UNIX_EPOCH).ok ()) {
+				Some (d) => write! (f, "{} expiry id={id} at {}.{:03}", self.seq, d.as_secs (), d.subsec_millis ()),
+				None     => write! (f, "{} expiry id={id} none", self.seq)
+			}
+		}
+	}
+}
+
+#[cfg (test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn changes () {
+		let mut t=Table::new ();
+		let all=t.changes (0).unwrap ();
+		t.add (Record::new (1, 10, "a")).unwrap ();
+		t.add (Record::new (2, 20, "b")).unwrap ();
+		assert! (t.add (Record::new (2, 0, "")).is_err ());  // no event for what failed
+		t.upd (Record::new (1, 11, "a")).unwrap ();
+		let late=t.changes (2).unwrap ();
+		t.rm (2).unwrap ();
+		t.add (Record::new (3, 30, "c")).unwrap ();
+		assert_eq! (t.delete_range (Column::Num, ..), 2);
+		assert_eq! (t.seq (), 7);
+
+		let evs:Vec<Event> = all.try_iter ().collect ();
+		assert! (evs.iter ().map (|e| e.seq).eq (1..=7));
+		assert_eq! (evs[2].change, Change::Update { before: Arc::new (Record::new (1, 10, "a")), after: Arc::new (Record::new (1, 11, "a")) });
+		assert_eq! (evs[3].change, Change::Delete (Arc::new (Record::new (2, 20, "b"))));
+		assert! (late.try_iter ().map (|e| e.seq).eq (3..=7));
+
+		// over the wire and back
+		for e in &evs {
+			assert_eq! (Event::decode (&e.encode ()).as_ref (), Some (e));
+		}
+		assert! (Event::decode (&evs[2].encode ()[..30]).is_none ());
+		assert_eq! (evs[2].to_string (), "3 update id=1, num=10, str=a    -> id=1, num=11, str=a   ");
+		for at in [Some (UNIX_EPOCH + Duration::from_millis (1_700_000_000_123)), None] {
+			let e=Event { seq: 9, change: Change::Expiry { id: 3, at } };
+			assert_eq! (Event::decode (&e.encode ()), Some (e));
+		}
+		assert_eq! (Event { seq: 9, change: Change::Expiry { id: 3, at: Some (UNIX_EPOCH + Duration::from_millis (1500)) } }.to_string (), "9 expiry id=3 at 1.500");
+
+		assert_eq! (t.changes (8).err (), Some (7));
+		assert_eq! ((Event::head_of (&Event::head (9, 7)), Event::decode (&Event::head (9, 7)), Event::head_of (&evs[0].encode ())), (Some ((9, 7)), None, None));
+		assert! (t.stream ()!=0 && t.stream ()!=Table::with_seed (0).stream ());
+
+		let (seq,rows,rx)=t.changes_full ();
+		t.add (Record::new (4, 0, "")).unwrap ();
+		assert_eq! ((seq, rows.len ()), (7, 0));
+		assert! (rx.try_iter ().map (|e| e.seq).eq ([8]));
+		let (_,rows,_)=t.changes_full ();
+		assert_eq! (rows, [Event { seq: 8, change: Change::Insert (Arc::new (Record::new (4, 0, ""))) }]);
+
+		// an expiry is a change of its own, a full stream has them after the rows
+		let at=Some (UNIX_EPOCH + Duration::from_secs (1<<40));
+		t.set_expiry (4, at).unwrap ();
+		assert_eq! (rx.try_iter ().map (|e| e.change).collect::<Vec<_>> (), [Change::Expiry { id: 4, at }]);
+		let (seq,rows,_)=t.changes_full ();
+		assert_eq! ((seq, &rows[1..]), (9, &[Event { seq: 9, change: Change::Expiry { id: 4, at } }][..]));
+		t.set_expiry (4, None).unwrap ();
+		assert! (ChangesReq::from_bytes (&ChangesReq::new (0, 3, 5, true, 1).to_bytes ()).is_some_and (|r| r.full && {r.from}==5 && {r.stream}==3));
+		for _ in 0..KEEP {
+			t.upd (Record::new (1, 0, "")).unwrap_or_default ();
+			t.add (Record::new (1, 0, "")).unwrap_or_default ();
+		}
+		let first=t.seq ()-KEEP as u64;
+		assert_eq! (t.changes (first-1).err (), Some (first));
+		assert_eq! (t.changes (first).unwrap ().try_iter ().count (), KEEP);
+		drop (t);
+		assert! (all.try_iter ().count ()>0 && all.recv ().is_err ());  // the table is gone, so is the stream
+	}
+}
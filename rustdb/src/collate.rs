@@ -0,0 +1,127 @@
+/* Collations: how the str column's index orders the strings.
+	Binary is by the bytes (for UTF-8 the same as by the code points), NoCase folds the ASCII letters,
+	Natural folds the case of any letter and takes the runs of digits by their value (a2 < a10).
+	The strings are fixed-size byte arrays and may be invalid UTF-8: Natural reads such bytes as U+FFFD.
+	The strings that collate equal still go in the order of their bytes, so the order stays total.
+	The filters keep comparing the bytes, so with other than Binary a condition on str is scanned for.
+ */
+
+use std::{
+	cmp::Ordering,
+	iter::Peekable,
+	str::Chars
+};
+
+use crate::*;
+
+#[derive (Copy,Clone,Debug,Default,PartialEq)]
+pub enum Collation {
+	#[default]
+	Binary,
+	NoCase,
+	Natural
+}
+
+impl Collation {
+	pub fn compare (&self, a:&[u8], b:&[u8])->Ordering {
+		match self {
+			Collation::Binary  => a.cmp (b),
+			Collation::NoCase  => a.iter ().map (u8::to_ascii_lowercase).cmp (b.iter ().map (u8::to_ascii_lowercase))
+			                      .then_with (|| a.cmp (b)),
+			Collation::Natural => natural (&String::from_utf8_lossy (a), &String::from_utf8_lossy (b))
+			                      .then_with (|| a.cmp (b))
+		}
+	}
+}
+
+fn natural (a:&str, b:&str)->Ordering {
+	let (mut a, mut b) = (a.chars ().peekable (), b.chars ().peekable ());
+
+	loop {
+		let o=match (a.peek (), b.peek ()) {
+			(None   , None   ) => return Ordering::Equal,
+			(None   , Some (_)) => return Ordering::Less,
+			(Some (_), None   ) => return Ordering::Greater,
+			(Some (c), Some (d)) if c.is_ascii_digit () && d.is_ascii_digit () => {
+				let (n,m) = (digits (&mut a), digits (&mut b));
+				n.len ().cmp (&m.len ()).then_with (|| n.cmp (&m))
+			}
+			(Some (&c), Some (&d)) => {
+				a.next ();
+				b.next ();
+				c.to_lowercase ().cmp (d.to_lowercase ())
+			}
+		};
+		if o.is_ne () {
+			return o
+		}
+	}
+}
+
+// The run of digits the chars start with, without the leading zeros
+fn digits (it:&mut Peekable<Chars>)->String {
+	let mut s=String::new ();
+	while let Some (c)=it.next_if (char::is_ascii_digit) {
+		if !(s.is_empty () && c=='0') {
+			s.push (c);
+		}
+	}
+	s
+}
+
+impl Table {
+	// Reorders the index of a str column by the collation. Refused for the other columns.
+	pub fn set_collation (&mut self, col:Column, coll:Collation)->Result<(),Error> {
+		if !matches! (self.fields.get (col as usize).ok_or (Error::Invalid)?.dt, Dt::Str) {
+			return Err (Error::Invalid)
+		}
+		self.rebuild (col, |ix| ix.coll = coll)
+	}
+
+	pub fn collation (&self, col:Column)->Collation {
+		self.fields[col as usize].coll
+	}
+}
+
+#[cfg (test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn collate () {
+		fn sorted<'a> (coll:Collation, v:&[&'a [u8]])->Vec<&'a [u8]> {
+			let mut v=v.to_vec ();
+			v.sort_by (|a,b| coll.compare (a,b));
+			v
+		}
+		let v:&[&[u8]] = &[b"b", b"a10", b"A2", b"a2", b"B", b"a02", "é".as_bytes (), "É".as_bytes (), b"\xFF\xFE", b"a"];
+
+		assert_eq! (sorted (Collation::Binary , v), [&b"A2"[..], b"B", b"a", b"a02", b"a10", b"a2", b"b", "É".as_bytes (), "é".as_bytes (), b"\xFF\xFE"]);
+		assert_eq! (sorted (Collation::NoCase , v), [&b"a"[..], b"a02", b"a10", b"A2", b"a2", b"B", b"b", "É".as_bytes (), "é".as_bytes (), b"\xFF\xFE"]);
+		assert_eq! (sorted (Collation::Natural, v), [&b"a"[..], b"A2", b"a02", b"a2", b"a10", b"B", b"b", "É".as_bytes (), "é".as_bytes (), b"\xFF\xFE"]);
+
+		let mut t=Table::new ();
+		for (id,s) in v.iter ().enumerate () {
+			let mut r=Record::new (id as u32, 0, "");
+			r.str[..s.len ()].copy_from_slice (s);
+			t.add (r).unwrap ();
+		}
+		assert! (t.set_collation (Column::Num, Collation::Natural).is_err ());
+		t.set_collation (Column::Str, Collation::Natural).unwrap ();
+		assert_eq! (t.collation (Column::Str), Collation::Natural);
+
+		// pages, filters and groups keep working over the reordered index, invalid bytes and all
+		let req=Request::new (Column::Str, 20);
+		let ids:Vec<u32> = t.fetch_recs (req).1.iter ().map (|r| r.id).collect ();
+		assert_eq! (ids, [9,5,2,3,1,4,0,7,6,8]);
+
+		let f=Filter::parse ("str like 'a%'").unwrap ();
+		let (resp,recs)=t.fetch_filtered (req, Some (&f));
+		assert! (resp.ok && recs.iter ().map (|r| r.id).eq ([9,5,3,1]));
+
+		assert_eq! (t.group_by (Column::Str, 1).iter ().map (|g| g.count).sum::<u32> (), 10);
+		t.set_collation (Column::Str, Collation::NoCase).unwrap ();
+		assert_eq! (t.group_by (Column::Str, 1).iter ().map (|g| g.count).collect::<Vec<_>> (), [5,2,2,1]);
+		assert! (t.iter ().all (|r| !r.to_string ().is_empty ()));
+	}
+}
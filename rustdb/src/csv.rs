@@ -0,0 +1,69 @@
+/* Import of records from CSV text, one `id,num,str` per line.
+	A header line (non-numeric id), blank lines and #-comments are skipped. \N is a NULL num or str.
+ */
+
+use std::io::{self,BufRead};
+
+use crate::*;
+
+pub const CSV_NULL:&str = "\\N";
+
+impl Table {
+	pub fn load_csv (r:impl BufRead)->io::Result<Self> {
+		let bad = |n:usize, msg:&str| {
+			io::Error::new (io::ErrorKind::InvalidData, format! ("line {n}: {msg}"))
+		};
+
+		let mut t=Table::new ();
+		for (n,line) in r.lines ().enumerate () {
+			let (n,line) = (n+1, line?);
+			let line=line.trim ();
+			if line.is_empty () || line.starts_with ('#') {
+				continue
+			}
+
+			let mut f=line.splitn (3, ',').map (str::trim);
+			let (id,num,s) = match (f.next (), f.next (), f.next ()) {
+				(Some (id), Some (num), Some (s)) => (id,num,s),
+				_ => return Err (bad (n, "expected id,num,str"))
+			};
+
+			let Ok (id)=id.parse::<u32> () else {
+				if n==1 {
+					continue  // header
+				}
+				return Err (bad (n, "id is not a u32"))
+			};
+			let mut r=Record::new (id, 0, if s==CSV_NULL {""} else {s});
+			match num {
+				CSV_NULL => r = r.null (Column::Num),
+				_    => r.num = num.parse::<i32> ().map_err (|_| bad (n, "num is not an i32"))?
+			}
+			if s==CSV_NULL {
+				r = r.null (Column::Str);
+			}
+
+			t.add (r).map_err (|_| bad (n, "duplicate id"))?;
+		}
+
+		Ok (t)
+	}
+}
+
+#[cfg (test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn load () {
+		let text="id,num,str\n3,-5,abcdef\n\n# comment\n1, 7 ,x\n4,\\N,\\N\n";
+		let t=Table::load_csv (text.as_bytes ()).unwrap ();
+		assert_eq! (t.tot (), 3);
+
+		let r:Vec<_> = t.iter ().map (|r| (r.id,r.num,r.str,r.nulls)).collect ();
+		assert_eq! (r, [(1,7,*b"x   ",0), (3,-5,*b"abcd",0), (4,0,[0;SLEN],0b110)]);
+
+		assert! (Table::load_csv ("1,2,a\n1,3,b\n".as_bytes ()).is_err ());
+		assert! (Table::load_csv ("1,2\n".as_bytes ()).is_err ());
+	}
+}
@@ -0,0 +1,255 @@
+/* A database: named tables, each behind a lock of its own, so that the requests to different tables
+	don't wait for each other (the map of them is only locked to look one up, create or drop it, never
+	while a table's lock is taken). A table is also known by an id, which is never reused: the binary requests
+	carry the id, the queries the name, so there's a map by either.
+	Every table has the same columns; its schema is how they're ordered and which expressions are indexed.
+	The tables made are seeded from the database's seed, so that a run replays with them as well.
+	A read-only database (a replica) has its tables changed in-process only, the statements can't.
+ */
+
+use rand::{Rng,SeedableRng,rngs::StdRng};
+use std::{
+	collections::{BTreeMap,HashMap},
+	sync::RwLock
+};
+
+use crate::*;
+
+pub const DEFAULT_TABLE:&str = "t";  // the one the server starts with
+const NAME_MAX:usize = 64;
+
+#[derive (Debug, Clone, Default, PartialEq)]
+pub struct Schema {
+	pub collation: Collation,           // of the str column
+	pub nulls    : Nulls,               // of every column
+	pub exprs    : Vec<(Column,Expr)>   // indexes on the expressions
+}
+
+impl Table {
+	pub fn with_schema (schema:&Schema, seed:u64)->Result<Self,String> {
+		let mut t=Table::with_seed (seed);
+		t.set_collation (Column::Str, schema.collation).unwrap ();
+		for col in [Column::Num, Column::Str] {
+			t.set_nulls (col, schema.nulls).unwrap ();
+		}
+		for &(col,expr) in &schema.exprs {
+			t.create_index (col,expr).map_err (|e| format! ("Can't index {expr:?} of {col:?}: {e}"))?;
+		}
+		Ok (t)
+	}
+}
+
+#[derive (Debug)]
+pub struct Database (RwLock<Tables>);
+
+#[derive (Debug)]
+struct Tables {
+	map : BTreeMap<String,(u32,Arc<RwLock<Table>>)>,
+	ids : HashMap<u32,Arc<RwLock<Table>>>,  // the same tables by id
+	next: u32,  // id of the next table
+	ro  : bool,
+	rng : StdRng  // that the tables' seeds come from
+}
+
+impl Default for Database {
+	fn default ()->Self {
+		Self::new ()
+	}
+}
+
+impl Database {
+	pub fn new ()->Self {
+		Self::with_seed (rand::thread_rng ().gen ())
+	}
+
+	pub fn with_seed (seed:u64)->Self {
+		Database (RwLock::new (Tables { map: BTreeMap::new (), ids: HashMap::new (), next: 0, ro: false, rng: StdRng::seed_from_u64 (seed) }))
+	}
+
+	pub fn set_read_only (&self, ro:bool) {
+		self.0.write ().unwrap ().ro = ro;
+	}
+
+	// A new empty table, its id
+	pub fn create (&self, name:&str, schema:&Schema)->Result<u32,String> {
+		let seed=self.0.write ().unwrap ().rng.gen ();
+		self.attach (name, Table::with_schema (schema, seed)?)
+	}
+
+	// A table made elsewhere (loaded, say) added under the name, its id
+	pub fn attach (&self, name:&str, t:Table)->Result<u32,String> {
+		if name.is_empty () || name.len ()>NAME_MAX || !name.chars ().all (|c| c.is_ascii_alphanumeric () || c=='_') {
+			return Err (format! ("Bad table name '{name}'"))
+		}
+
+		let mut tables=self.0.write ().unwrap ();
+		if tables.map.contains_key (name) {
+			return Err (format! ("Table {name} exists"))
+		}
+		let (id,t) = (tables.next, Arc::new (RwLock::new (t)));
+		tables.next += 1;
+		tables.ids.insert (id, Arc::clone (&t));
+		tables.map.insert (name.to_string (), (id, t));
+
+		Ok (id)
+	}
+
+	// Those still holding the table (a request being served) finish with it, then it's gone
+	pub fn drop (&self, name:&str)->Result<(),String> {
+		let mut tables=self.0.write ().unwrap ();
+		let (id,_) = tables.map.remove (name).ok_or (format! ("No table {name}"))?;
+		tables.ids.remove (&id);
+		Ok (())
+	}
+
+	// (id, name, rows) of every table, by name: each counted once the map is let go, not to hold it up
+	pub fn list (&self)->Vec<(u32,String,u32)> {
+		let tables:Vec<(u32,String,Arc<RwLock<Table>>)> = self.0.read ().unwrap ().map.iter ().map (|(name,(id,t))| (*id, name.clone (), Arc::clone (t))).collect ();
+		tables.into_iter ().map (|(id,name,t)| (id, name, t.read ().unwrap ().tot ())).collect ()
+	}
+
+	pub fn get (&self, name:&str)->Option<Arc<RwLock<Table>>> {
+		self.0.read ().unwrap ().map.get (name).map (|(_,t)| Arc::clone (t))
+	}
+
+	pub fn by_id (&self, id:u32)->Option<Arc<RwLock<Table>>> {
+		self.0.read ().unwrap ().ids.get (&id).map (Arc::clone)
+	}
+
+	pub fn id (&self, name:&str)->Option<u32> {
+		self.0.read ().unwrap ().map.get (name).map (|(id,_)| *id)
+	}
+
+	// The answer to a query message: a SELECT from the table it names, or a statement on the tables
+	pub fn answer (&self, tag:u32, text:&str)->Vec<u8> {
+		let done=|text:String| (0, 0, vec![], text);
+
+		query::answer (tag, Statement::parse (text).and_then (|st| match st {
+			Statement::Create (..) | Statement::Drop (_) if self.0.read ().unwrap ().ro => Err ("Read-only".to_string ()),
+			Statement::Select (q) => {
+				let t=self.get (&q.table).ok_or (format! ("No table {}", q.table))?;
+				let t=t.read ().unwrap ();
+				t.select (&q)
+			}
+			Statement::Create (name,schema) => self.create (&name, &schema).map (|id| done (format! ("Table {name} created, id {id}\n"))),
+			Statement::Drop   (name)        => self.drop (&name).map (|_| done (format! ("Table {name} dropped\n"))),
+			Statement::Show                 => {
+				let mut rows=vec![["id","name","rows"].map (String::from).to_vec ()];
+				rows.extend (self.list ().into_iter ().map (|(id,name,tot)| vec![id.to_string (), name, tot.to_string ()]));
+				Ok (done (query::columns (&rows)))
+			}
+			Statement::Memory (name) => {
+				let t=self.get (&name).ok_or (format! ("No table {name}"))?;
+				let m=t.read ().unwrap ().memory_usage ();
+				Ok (done (m.text ()))
+			}
+			Statement::Verify (name) => {
+				let t=self.get (&name).ok_or (format! ("No table {name}"))?;
+				let t=t.read ().unwrap ();
+				t.verify ().map (|_| done (format! ("Table {name} is consistent: {} rows\n", t.tot ())))
+			}
+			Statement::Replication => Err ("Not a replica".to_string ())
+		}))
+	}
+}
+
+#[cfg (test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn tables () {
+		let db=Database::new ();
+		let mut t=Table::new ();
+		t.add (Record::new (1, 2, "x")).unwrap ();
+		assert_eq! (db.attach (DEFAULT_TABLE, t), Ok (0));
+
+		let schema=Schema { collation: Collation::NoCase, nulls: Nulls::First, exprs: vec![(Column::Num,Expr::Abs)] };
+		assert_eq! (db.create ("u", &schema), Ok (1));
+		for bad in ["u", "", "a b", "x;"] {
+			assert! (db.create (bad, &Schema::default ()).is_err (), "{bad}");
+		}
+		assert! (db.create ("v", &Schema { exprs: vec![(Column::Id,Expr::Lower)], ..Default::default () }).is_err ());
+
+		// each table is on its own: writing one while the other's read lock is held
+		let u=db.by_id (1).unwrap ();
+		let held=db.get (DEFAULT_TABLE).unwrap ();
+		let held=held.read ().unwrap ();
+		for id in 0..5 {
+			u.write ().unwrap ().add (Record::new (id, -(id as i32), "a").null (Column::Str)).unwrap ();
+		}
+		assert_eq! ((held.tot (), db.list ()), (1, vec![(0,"t".to_string (),1), (1,"u".to_string (),5)]));
+		drop (held);
+
+		let u=u.read ().unwrap ();
+		assert_eq! ((u.collation (Column::Str), u.nulls (Column::Num)), (Collation::NoCase, Nulls::First));
+		drop (u);
+
+		assert_eq! (db.drop ("u"), Ok (()));
+		assert! (db.drop ("u").is_err () && db.by_id (1).is_none () && db.get ("u").is_none ());
+		assert_eq! (db.create ("u", &Schema::default ()), Ok (2));  // the ids aren't reused
+		assert_eq! (db.id ("u"), Some (2));
+
+		// a list waiting on a table's lock doesn't keep the others from the map
+		let busy=db.get (DEFAULT_TABLE).unwrap ();
+		let busy=busy.write ().unwrap ();
+		std::thread::scope (|s| {
+			let list=s.spawn (|| db.list ().len ());
+			std::thread::sleep (std::time::Duration::from_millis (50));
+			assert! (db.create ("w", &Schema::default ()).is_ok_and (|id| db.by_id (id).is_some ()));
+			drop (busy);
+			assert! (list.join ().unwrap ()>=2);
+		});
+
+		// the tables created replay with the seed: the same levels, so the same bytes of nodes
+		let mem=|seed| {
+			let db=Database::with_seed (seed);
+			let t=db.by_id (db.create ("x", &Schema::default ()).unwrap ()).unwrap ();
+			let mut t=t.write ().unwrap ();
+			for id in 0..1000 {
+				t.add (Record::new (id, 0, "")).unwrap ();
+			}
+			t.memory_usage ().total ()
+		};
+		assert! (mem (5)==mem (5) && mem (5)!=mem (6));
+	}
+
+	#[test]
+	fn statements () {
+		assert_eq! (Statement::parse ("create table x collate natural index lower(str) nulls first index abs(num);"),
+		            Ok (Statement::Create ("x".to_string (), Schema {
+			            collation: Collation::Natural,
+			            nulls    : Nulls::First,
+			            exprs    : vec![(Column::Str,Expr::Lower), (Column::Num,Expr::Abs)]
+		            })));
+		assert_eq! (Statement::parse ("DROP TABLE x"), Ok (Statement::Drop ("x".to_string ())));
+		assert_eq! (Statement::parse ("show tables"), Ok (Statement::Show));
+		assert_eq! (Statement::parse ("SHOW REPLICATION;"), Ok (Statement::Replication));
+		assert_eq! (Statement::parse ("show memory t"), Ok (Statement::Memory ("t".to_string ())));
+		assert_eq! (Statement::parse ("verify table t;"), Ok (Statement::Verify ("t".to_string ())));
+		assert! (matches! (Statement::parse ("select * from x"), Ok (Statement::Select (q)) if q.table=="x"));
+		for bad in ["create x", "create table", "create table x collate y", "create table x index abs(str)", "create table x index abs(id)", "drop x", "show", "show tables x", "show x", "show memory", "verify t", "insert"] {
+			assert! (Statement::parse (bad).is_err (), "{bad}");
+		}
+
+		let db=Database::new ();
+		let text=|a:Vec<u8>| (Response::from_bytes (&a).unwrap ().ok, String::from_utf8 (a[RESP_SZ..].to_vec ()).unwrap ());
+		assert_eq! (text (db.answer (1, "create table t")), (true, "Table t created, id 0\n".to_string ()));
+		assert! (text (db.answer (2, "create table u nulls first")).0);
+		db.get ("u").unwrap ().write ().unwrap ().add (Record::new (3, 4, "y")).unwrap ();
+
+		let a=db.answer (3, "select * from u");
+		assert_eq! ((Response::records (&a).iter ().map (|r| r.id).collect::<Vec<_>> (), {Response::from_bytes (&a).unwrap ().NS}), (vec![3], 1));
+		assert_eq! (text (db.answer (4, "select count(*) from t")), (true, "count(*)\n0\n".to_string ()));
+		assert_eq! (text (db.answer (5, "show tables")), (true, "id            name          rows\n0             t             0\n1             u             1\n".to_string ()));
+		let (ok,mem)=text (db.answer (5, "show memory u"));
+		assert! (ok && mem.starts_with ("part") && mem.contains ("index str"), "{mem}");
+		assert_eq! (text (db.answer (5, "verify table u")), (true, "Table u is consistent: 1 rows\n".to_string ()));
+		assert! (text (db.answer (6, "drop table u")).0);
+		assert_eq! (text (db.answer (7, "select * from u")), (false, "No table u".to_string ()));
+
+		db.set_read_only (true);
+		assert_eq! (text (db.answer (8, "create table v")), (false, "Read-only".to_string ()));
+		assert! (text (db.answer (9, "show tables")).0 && db.create ("v", &Schema::default ()).is_ok ());
+	}
+}
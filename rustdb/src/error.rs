@@ -0,0 +1,26 @@
+/* What a change of a table is refused for */
+
+use std::fmt;
+
+#[derive (Debug, Copy,Clone, PartialEq, Eq)]
+pub enum Error {
+	Exists,                          // a row with the id is there already
+	NullId,                          // the id is the primary key, it's never NULL
+	NotFound,                        // no row with the id
+	Invalid,                         // the column or the expression doesn't apply
+	Full {used: usize, cap: usize}   // the table has got to its memory cap (bytes)
+}
+
+impl fmt::Display for Error {
+	fn fmt (&self, f:&mut fmt::Formatter<'_>)->fmt::Result {
+		match self {
+			Error::Exists          => write! (f, "the id exists"),
+			Error::NullId          => write! (f, "the id is NULL"),
+			Error::NotFound        => write! (f, "no such id"),
+			Error::Invalid         => write! (f, "doesn't apply to the column"),
+			Error::Full {used,cap} => write! (f, "out of memory: {used} bytes used of {cap}")
+		}
+	}
+}
+
+impl std::error::Error for Error {}
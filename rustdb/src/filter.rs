@@ -0,0 +1,620 @@
+/* Filters on the paged view: comparisons of the columns with constants, string prefixes, IS [NOT] NULL,
+	AND/OR/NOT.
+	Text form:  num >= -5 and not (str = 'ab' or str like 'x%') or id < 100 or num is null
+	As in SQL, a comparison with a NULL is unknown, and so is NOT of it: such rows don't pass.
+	On the wire it goes after the request, in prefix order (see to_bytes).
+ */
+
+use std::{
+	cmp::{self,Ordering},
+	fmt,
+	sync::Arc
+};
+
+use crate::*;
+
+#[derive (Debug, Copy,Clone, PartialEq)]
+#[repr (u8)]
+pub enum Op { Eq, Ne, Lt, Le, Gt, Ge, NumOp }
+
+#[derive (Debug, Clone, PartialEq)]
+pub enum Filter {
+	Cmp    (Column, Op, Record),  // only the column's field of the record matters
+	Prefix (Vec<u8>),             // of the str column
+	Null   (Column),
+	And    (Box<Filter>, Box<Filter>),
+	Or     (Box<Filter>, Box<Filter>),
+	Not    (Box<Filter>)
+}
+
+const MAX_NODES:usize = 64;  // of a filter from the network, so that decoding it can't go too deep
+
+// Wire tags of the nodes
+const CMP   :u8 = 0;
+const PREFIX:u8 = 1;
+const AND   :u8 = 2;
+const OR    :u8 = 3;
+const NOT   :u8 = 4;
+const NULL  :u8 = 5;
+
+fn field_cmp (col:Column, a:&Record, b:&Record)->Ordering {
+	match col {
+		Column::Id  => a.id .cmp (&b.id ),
+		Column::Num => a.num.cmp (&b.num),
+		_           => a.str.cmp (&b.str)
+	}
+}
+
+impl Filter {
+	pub fn eval (&self, r:&Record)->bool {
+		self.test (r)==Some (true)
+	}
+
+	// True, false, or unknown (None) when it comes to a NULL
+	fn test (&self, r:&Record)->Option<bool> {
+		use Filter::*;
+		match self {
+			Cmp (col,_,_) if r.is_null (*col)      => None,
+			Prefix (_) if r.is_null (Column::Str) => None,
+			Cmp (col,op,v) => {
+				let c=field_cmp (*col, r, v);
+				Some (match op {
+					Op::Eq => c.is_eq (),
+					Op::Ne => c.is_ne (),
+					Op::Lt => c.is_lt (),
+					Op::Le => c.is_le (),
+					Op::Gt => c.is_gt (),
+					_      => c.is_ge ()
+				})
+			}
+			Prefix (p) => Some (r.str.starts_with (p)),
+			Null (col) => Some (r.is_null (*col)),
+			And (a,b)  => match (a.test (r), b.test (r)) {
+				(Some (false),_) | (_,Some (false)) => Some (false),
+				(Some (true),Some (true))           => Some (true),
+				_                                   => None
+			},
+			Or  (a,b)  => match (a.test (r), b.test (r)) {
+				(Some (true),_) | (_,Some (true))   => Some (true),
+				(Some (false),Some (false))         => Some (false),
+				_                                   => None
+			},
+			Not (a)    => a.test (r).map (|x| !x)
+		}
+	}
+
+	/* The part of the index (as the positions a..b, 0-based, b exclusive) that the filter confines
+	   the rows to, by its conditions on the indexed column that are ANDed at the top.
+	   Whether the filter is exactly that range, i.e. there's nothing left to check on the rows. */
+	pub(crate) fn range (&self, col:Column, ix:&Index, tot:u32)->(u32,u32,bool) {
+		// an expression's order (or a collation's) says nothing of the ranges of the column's values
+		if ix.expr!=Expr::Field || ix.coll!=Collation::Binary {
+			return (0, tot, false)
+		}
+
+		let (mut a, mut b) = (0, tot);
+		let mut exact=true;
+
+		// A bound is a key that sorts before (after) all the records with the equal field
+		let key=|v:&Record, last:bool| {
+			let mut k=*v;
+			if col!=Column::Id {
+				k.id = if last {u32::MAX} else {0};
+			}
+			k
+		};
+
+		// The values and the NULLs are apart in the index, the comparisons only ever pass the values
+		let (nulls,vals) = ix.null_range (tot);
+		let within=|(a,b):(u32,u32), (x,y):(u32,u32)| (cmp::max (a,x), cmp::min (b,y));
+
+		let mut conj=vec![self];
+		while let Some (f)=conj.pop () {
+			match f {
+				Filter::And (x,y) => { conj.push (x); conj.push (y); }
+				Filter::Null (c) if *c==col => (a,b) = within ((a,b), nulls),
+				Filter::Not (x) if **x==Filter::Null (col) => (a,b) = within ((a,b), vals),
+				Filter::Cmp (c,op,v) if *c==col && *op!=Op::Ne => {
+					(a,b) = within ((a,b), vals);
+					match op {
+						Op::Eq => {
+							a = cmp::max (a, ix.count (&key (v,false), false));
+							b = cmp::min (b, ix.count (&key (v,true ), true ));
+						}
+						Op::Lt => b = cmp::min (b, ix.count (&key (v,false), false)),
+						Op::Le => b = cmp::min (b, ix.count (&key (v,true ), true )),
+						Op::Gt => a = cmp::max (a, ix.count (&key (v,true ), true )),
+						_      => a = cmp::max (a, ix.count (&key (v,false), false))
+					}
+				}
+				Filter::Prefix (p) if col==Column::Str && p.len ()<=SLEN => {
+					(a,b) = within ((a,b), vals);
+					let mut lo=Record::default ();
+					let mut hi=Record { id: u32::MAX, ..Default::default () };
+					lo.str = [0x00;SLEN];
+					hi.str = [0xFF;SLEN];
+					lo.str[..p.len ()].copy_from_slice (p);
+					hi.str[..p.len ()].copy_from_slice (p);
+
+					a = cmp::max (a, ix.count (&lo,false));
+					b = cmp::min (b, ix.count (&hi,true ));
+				}
+				_ => exact=false
+			}
+		}
+
+		(a, cmp::max (a,b), exact)
+	}
+
+	pub fn to_bytes (&self)->Vec<u8> {
+		let mut buf=vec![];
+		self.encode (&mut buf);
+		buf
+	}
+
+	fn encode (&self, buf:&mut Vec<u8>) {
+		use Filter::*;
+		match self {
+			Cmp (col,op,v) => {
+				buf.extend_from_slice (&[CMP, *col as u8, *op as u8]);
+				buf.extend_from_slice (&v.id .to_ne_bytes ());
+				buf.extend_from_slice (&v.num.to_ne_bytes ());
+				buf.extend_from_slice (&v.str);
+			}
+			Prefix (p) => {
+				buf.extend_from_slice (&[PREFIX, p.len () as u8]);
+				buf.extend_from_slice (p);
+			}
+			Null (col) => buf.extend_from_slice (&[NULL, *col as u8]),
+			And (a,b) | Or (a,b) => {
+				buf.push (if matches! (self, And (..)) {AND} else {OR});
+				a.encode (buf);
+				b.encode (buf);
+			}
+			Not (a) => {
+				buf.push (NOT);
+				a.encode (buf);
+			}
+		}
+	}
+
+	// Filters arrive from the network, so everything is checked, and the size is limited
+	pub fn from_bytes (buf:&[u8])->Option<Self> {
+		let mut buf=buf;
+		let mut nodes=0;
+		let f=Self::decode (&mut buf, &mut nodes)?;
+
+		buf.is_empty ().then_some (f)
+	}
+
+	fn decode (buf:&mut &[u8], nodes:&mut usize)->Option<Self> {
+		*nodes += 1;
+		if *nodes>MAX_NODES {
+			return None
+		}
+
+		let (&tag,rest) = buf.split_first ()?;
+		*buf = rest;
+		let mut take=|n:usize| {
+			let (a,b) = (buf.get (..n)?, buf.get (n..)?);
+			*buf = b;
+			Some (a)
+		};
+
+		Some (match tag {
+			CMP => {
+				let h=take (2)?;
+				if h[0]>=Column::NumCol as u8 || h[1]>=Op::NumOp as u8 {
+					return None
+				}
+				let (col,op) = unsafe { (mem::transmute::<u8,Column> (h[0]), mem::transmute::<u8,Op> (h[1])) };
+
+				let v=take (8+SLEN)?;
+				let rec=Record {
+					id : u32::from_ne_bytes (v[0..4].try_into ().unwrap ()),
+					num: i32::from_ne_bytes (v[4..8].try_into ().unwrap ()),
+					str: v[8..].try_into ().unwrap (),
+					nulls: 0
+				};
+				Filter::Cmp (col,op,rec)
+			}
+			PREFIX => {
+				let n=take (1)?[0] as usize;
+				Filter::Prefix (take (n)?.to_vec ())
+			}
+			AND|OR => {
+				let a=Box::new (Self::decode (buf,nodes)?);
+				let b=Box::new (Self::decode (buf,nodes)?);
+				if tag==AND {Filter::And (a,b)} else {Filter::Or (a,b)}
+			}
+			NOT => Filter::Not (Box::new (Self::decode (buf,nodes)?)),
+			NULL => {
+				let c=take (1)?[0];
+				if c>=Column::NumCol as u8 {
+					return None
+				}
+				Filter::Null (unsafe { mem::transmute::<u8,Column> (c) })
+			}
+			_ => return None
+		})
+	}
+
+	/* The text form:
+		expr := and {OR and}     and := unary {AND unary}     unary := NOT unary | '(' expr ')' | cond
+		cond := col op value | str LIKE 'prefix%' | col IS [NOT] NULL
+		op   := = | != | <> | < | <= | > | >=
+	   Keywords are case-insensitive, strings are quoted with '' (a quote inside is doubled). */
+	pub fn parse (s:&str)->Result<Self,String> {
+		let mut p=Parser { toks: lex (s)?, i: 0 };
+		let f=p.expr ()?;
+		match p.toks.get (p.i) {
+			None    => Ok (f),
+			Some (t) => Err (format! ("Unexpected {t}"))
+		}
+	}
+}
+
+impl fmt::Display for Filter {
+	fn fmt (&self, f:&mut fmt::Formatter<'_>)->fmt::Result {
+		use Filter::*;
+		match self {
+			Cmp (col,op,v) => {
+				let op=["=","!=","<","<=",">",">="][*op as usize];
+				match col {
+					Column::Id  => write! (f, "id {op} {}", v.id),
+					Column::Num => write! (f, "num {op} {}", v.num),
+					_           => write! (f, "str {op} '{}'", String::from_utf8_lossy (&v.str).trim_end ().replace ('\'', "''"))
+				}
+			}
+			Prefix (p) => write! (f, "str like '{}%'", String::from_utf8_lossy (p).replace ('\'', "''")),
+			Null (col) => write! (f, "{} is null", ["id","num","str"][*col as usize]),
+			And (a,b)  => write! (f, "({a} and {b})"),
+			Or  (a,b)  => write! (f, "({a} or {b})"),
+			Not (a)    => write! (f, "not {a}")
+		}
+	}
+}
+
+#[derive (Debug, Clone, PartialEq)]
+pub(crate) enum Tok {
+	Word (String),  // keywords, column names, numbers
+	Str  (String),
+	Sym  (&'static str)
+}
+
+impl fmt::Display for Tok {
+	fn fmt (&self, f:&mut fmt::Formatter<'_>)->fmt::Result {
+		match self {
+			Tok::Word (w) => write! (f, "'{w}'"),
+			Tok::Str  (s) => write! (f, "string '{s}'"),
+			Tok::Sym  (s) => write! (f, "'{s}'")
+		}
+	}
+}
+
+pub(crate) fn lex (s:&str)->Result<Vec<Tok>,String> {
+	const SYMS:[&str;11] = ["<=", ">=", "<>", "!=", "=", "<", ">", "(", ")", ",", ";"];
+
+	let mut toks=vec![];
+	let mut s=s.trim_start ();
+	while let Some (c)=s.chars ().next () {
+		if let Some (sym)=SYMS.iter ().find (|&&sym| s.starts_with (sym)) {
+			toks.push (Tok::Sym (sym));
+			s = &s[sym.len ()..];
+		}
+		else if c=='\'' {
+			let mut v=String::new ();
+			let mut rest=&s[1..];
+			loop {
+				let end=rest.find ('\'').ok_or ("Unterminated string")?;
+				v += &rest[..end];
+				rest = &rest[end+1..];
+				if !rest.starts_with ('\'') {
+					break
+				}
+				v.push ('\'');
+				rest = &rest[1..];
+			}
+			toks.push (Tok::Str (v));
+			s = rest;
+		}
+		else if c.is_alphanumeric () || c=='_' || c=='-' || c=='*' || c=='.' {
+			let end=s.find (|c:char| !(c.is_alphanumeric () || c=='_' || c=='-' || c=='*' || c=='.')).unwrap_or (s.len ());
+			toks.push (Tok::Word (s[..end].to_string ()));
+			s = &s[end..];
+		}
+		else {
+			return Err (format! ("Unexpected '{c}'"))
+		}
+		s = s.trim_start ();
+	}
+
+	Ok (toks)
+}
+
+pub(crate) struct Parser {
+	pub toks: Vec<Tok>,
+	pub i   : usize
+}
+
+impl Parser {
+	pub fn peek (&self)->Option<&Tok> {
+		self.toks.get (self.i)
+	}
+
+	pub fn next (&mut self)->Result<Tok,String> {
+		let t=self.peek ().cloned ().ok_or ("Unexpected end of the filter")?;
+		self.i += 1;
+		Ok (t)
+	}
+
+	// Consume the keyword if it's next
+	pub fn kw (&mut self, kw:&str)->bool {
+		let yes=matches! (self.peek (), Some (Tok::Word (w)) if w.eq_ignore_ascii_case (kw));
+		if yes {
+			self.i += 1;
+		}
+		yes
+	}
+
+	pub fn expr (&mut self)->Result<Filter,String> {
+		let mut f=self.and ()?;
+		while self.kw ("or") {
+			f = Filter::Or (Box::new (f), Box::new (self.and ()?));
+		}
+		Ok (f)
+	}
+
+	fn and (&mut self)->Result<Filter,String> {
+		let mut f=self.unary ()?;
+		while self.kw ("and") {
+			f = Filter::And (Box::new (f), Box::new (self.unary ()?));
+		}
+		Ok (f)
+	}
+
+	fn unary (&mut self)->Result<Filter,String> {
+		if self.kw ("not") {
+			return Ok (Filter::Not (Box::new (self.unary ()?)))
+		}
+		if self.peek ()==Some (&Tok::Sym ("(")) {
+			self.i += 1;
+			let f=self.expr ()?;
+			return match self.next ()? {
+				Tok::Sym (")") => Ok (f),
+				t              => Err (format! ("Expected ')' instead of {t}"))
+			}
+		}
+		self.cond ()
+	}
+
+	pub fn column (&mut self)->Result<Column,String> {
+		match self.next ()? {
+			Tok::Word (w) => match w.to_ascii_lowercase ().as_str () {
+				"id"  => Ok (Column::Id),
+				"num" => Ok (Column::Num),
+				"str" => Ok (Column::Str),
+				_     => Err (format! ("Unknown column '{w}'"))
+			},
+			t => Err (format! ("Expected a column instead of {t}"))
+		}
+	}
+
+	fn cond (&mut self)->Result<Filter,String> {
+		let col=self.column ()?;
+
+		if self.kw ("is") {
+			let not=self.kw ("not");
+			if !self.kw ("null") {
+				return Err ("Expected NULL".to_string ())
+			}
+			let f=Filter::Null (col);
+			return Ok (if not {Filter::Not (Box::new (f))} else {f})
+		}
+
+		if col==Column::Str && self.kw ("like") {
+			return match self.next ()? {
+				Tok::Str (s) if s.ends_with ('%') && s.matches ('%').count ()==1 && s.len ()<=u8::MAX as usize => {
+					Ok (Filter::Prefix (s.trim_end_matches ('%').as_bytes ().to_vec ()))
+				}
+				t => Err (format! ("Expected a 'prefix%' string instead of {t}"))
+			}
+		}
+
+		let op=match self.next ()? {
+			Tok::Sym ("=")         => Op::Eq,
+			Tok::Sym ("!="|"<>")   => Op::Ne,
+			Tok::Sym ("<")         => Op::Lt,
+			Tok::Sym ("<=")        => Op::Le,
+			Tok::Sym (">")         => Op::Gt,
+			Tok::Sym (">=")        => Op::Ge,
+			t => return Err (format! ("Expected a comparison instead of {t}"))
+		};
+
+		let val=self.next ()?;
+		let mut v=Record::default ();
+		match (col,&val) {
+			(Column::Id , Tok::Word (w)) => v.id  = w.parse ().map_err (|_| format! ("Bad id {val}"))?,
+			(Column::Num, Tok::Word (w)) => v.num = w.parse ().map_err (|_| format! ("Bad num {val}"))?,
+			(Column::Str, Tok::Str  (s) | Tok::Word (s)) => v = Record::new (0, 0, s),
+			_ => return Err (format! ("Bad value {val}"))
+		}
+
+		Ok (Filter::Cmp (col,op,v))
+	}
+}
+
+/* The rows a request pages over: a part of the sort index, or those records of it that passed a filter.
+   A part of the index may have some rows hidden (expired), that are then skipped over by their positions. */
+pub(crate) enum Rows<'a> {
+	Range (u32,u32),             // positions a..b of the index, 0-based
+	Hide  (u32,u32,Vec<u32>),    // the same but for those positions (sorted, within a..b)
+	Scan  (Vec<&'a Arc<Record>>)
+}
+
+impl Rows<'_> {
+	pub fn len (&self)->u32 {
+		match self {
+			Rows::Range (a,b)   => b-a,
+			Rows::Hide  (a,b,h) => b-a - h.len () as u32,
+			Rows::Scan  (v)     => v.len () as u32
+		}
+	}
+
+	// The positions in the index of the rows of the range that are hidden
+	pub fn hidden_pos (&self)->&[u32] {
+		match self {
+			Rows::Hide (_,_,h) => h,
+			_                  => &[]
+		}
+	}
+
+	// The rows of the range that are hidden, those before pos (0-based, in the index)
+	pub fn hidden (&self, pos:u32)->u32 {
+		self.hidden_pos ().partition_point (|&x| x<pos) as u32
+	}
+
+	// N rows (or less if there aren't as many) starting at pos (1-based, in the sort direction)
+	pub fn take (&self, ix:&Index, bw:bool, pos:u32, N:u32)->Vec<Arc<Record>> {
+		let tot=self.len ();
+		if pos==0 || pos>tot {
+			return vec![]
+		}
+		let pos=if bw {tot-pos + 1} else {pos};                  // in the index order
+		let min=cmp::min (N, if bw {pos} else {tot-pos+1});
+
+		match self {
+			Rows::Range (a,_) => {
+				let mut data=Vec::with_capacity (min as usize);
+				let mut cur=ix.lookup (a+pos) as *const Node;
+
+				for _ in 0..min {
+					// the last step may go past the end (null), which is fine as long as it isn't dereferenced
+					let node=unsafe { &*cur };
+					data.push (node.elem.clone ());
+
+					cur = if bw {node.prev.0} else {node.nxt (0)};
+				}
+				data
+			}
+			Rows::Hide (a,_,h) => {
+				// the position in the index of the pos-th row that isn't hidden: pos plus the hidden ones up to it
+				let mut p=a+pos;
+				loop {
+					let q=a+pos + h.partition_point (|&x| x<p) as u32;
+					if q==p {
+						break
+					}
+					p = q;
+				}
+
+				let mut data=Vec::with_capacity (min as usize);
+				let mut cur=ix.lookup (p) as *const Node;
+				let mut at=p-1;  // 0-based position of cur
+				while (data.len () as u32)<min {
+					let node=unsafe { &*cur };
+					if h.binary_search (&at).is_err () {
+						data.push (node.elem.clone ());
+					}
+					(cur,at) = if bw {(node.prev.0, at.wrapping_sub (1))} else {(node.nxt (0), at+1)};
+				}
+				data
+			}
+			Rows::Scan (v) => {
+				let (pos,min) = (pos as usize, min as usize);
+				if bw {
+					v[pos-min..pos].iter ().rev ().map (|&r| r.clone ()).collect ()
+				}
+				else {
+					v[pos-1..pos-1+min].iter ().map (|&r| r.clone ()).collect ()
+				}
+			}
+		}
+	}
+
+	// The number of rows before the key (or up to it, inclusive)
+	pub fn count (&self, ix:&Index, key:&Record, incl:bool)->u32 {
+		match self {
+			Rows::Range (a,b)   => ix.count (key,incl).clamp (*a,*b) - a,
+			Rows::Hide  (a,b,h) => {
+				let n=ix.count (key,incl).clamp (*a,*b);
+				n-a - h.partition_point (|&x| x<n) as u32
+			}
+			Rows::Scan  (v)   => v.partition_point (|r| {
+				let c=ix.comp (r,key);
+				c.is_lt () || (incl && c.is_eq ())
+			}) as u32
+		}
+	}
+}
+
+#[cfg (test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse () {
+		let f=Filter::parse ("num>=-5 AND not (str = 'a''b' or str like 'x%') or id<>100").unwrap ();
+		assert_eq! (f.to_string (), "((num >= -5 and not (str = 'a''b' or str like 'x%')) or id != 100)");
+		assert_eq! (Filter::from_bytes (&f.to_bytes ()), Some (f.clone ()));
+		assert_eq! (Filter::parse (&f.to_string ()), Ok (f));
+
+		for bad in ["", "num", "num >", "num > x", "foo = 1", "str like 'a'", "(id = 1", "id = 1 id", "id = 'a"] {
+			assert! (Filter::parse (bad).is_err (), "{bad}");
+		}
+
+		let f=Filter::parse ("id=1 or id=2").unwrap ().to_bytes ();
+		assert! (Filter::from_bytes (&f[..f.len ()-1]).is_none ());
+		assert! (Filter::from_bytes (&[NOT;MAX_NODES+1]).is_none ());
+	}
+
+	// Paging over the filtered rows, by positions and by cursors, against the plain filtered list
+	#[test]
+	fn fetch () {
+		let mut t=Table::new ();
+		for id in 0..500 {
+			let s=["ab","abc","b","ba","c"][id as usize % 5];
+			t.add (Record::new (id, (id as i32*37)%101-50, s)).unwrap ();
+		}
+
+		for (expr,exact) in [("num > -10 and num <= 20", Column::Num), ("str like 'ab%'", Column::Str),
+		                     ("id >= 100 and id < 130", Column::Id), ("num = 7", Column::Num),
+		                     ("num < 0 or str = 'c'", Column::NumCol), ("not str like 'b%' and id > 250", Column::NumCol)] {
+			let f=Filter::parse (expr).unwrap ();
+
+			for col in [Column::Id, Column::Num, Column::Str] {
+				let ix=&t.fields[col as usize];
+				assert_eq! (f.range (col, ix, t.tot ()).2, col==exact, "{expr}");
+
+				for bw in [false,true] {
+					let mut want:Vec<u32> = ix.iter ().filter (|r| f.eval (r)).map (|r| r.id).collect ();
+					if bw {
+						want.reverse ();
+					}
+
+					let req=Request { bw, ..Request::new (col, 7) };
+					let (resp,recs)=t.fetch_filtered (req, Some (&f));
+					assert! (resp.ok);
+					assert_eq! (resp.NS as usize, cmp::max (1, (want.len () as i64)-6) as usize);
+
+					let mut got=vec![];
+					for CS in (0..resp.NS).step_by (7).chain ([resp.NS-1]) {
+						let (_,recs)=t.fetch_filtered (Request { CS, NS: resp.NS, ..req }, Some (&f));
+						got.extend (recs.iter ().map (|r| r.id).filter (|id| !got.contains (id)).collect::<Vec<_>> ());
+					}
+					assert_eq! (got, want, "{expr}");
+
+					let mut got:Vec<u32> = recs.iter ().map (|r| r.id).collect ();
+					let mut last=recs.last ().map (|r| **r);
+					while let Some (key)=last {
+						let (_,recs)=t.fetch_filtered (Request { cur: Cursor::After, key, ..req }, Some (&f));
+						let new:Vec<_> = recs.iter ().filter (|r| !got.contains (&r.id)).map (|r| r.id).collect ();
+						last = if new.is_empty () {None} else {recs.last ().map (|r| **r)};
+						got.extend (new);
+					}
+					assert_eq! (got, want, "{expr}");
+				}
+			}
+		}
+	}
+}
@@ -0,0 +1,255 @@
+/* Histograms: the rows counted per value of a column, or per bucket of values.
+	Buckets are ranges of `bucket` numbers (aligned to its multiples), or for str the first `bucket` bytes.
+	Under NoCase the str groups fold the case; under Natural the prefixes aren't contiguous in the index,
+	so the groups are whole values.
+	The groups are found by jumping along the column's index, so it takes O(groups * log n).
+	The rows expired but not reaped yet aren't counted, a group of only those isn't there.
+	On the wire a GroupReq goes instead of a Request; the response carries the number of groups in NS
+	and the bucket used in CS, followed by (at most GROUP_MAX) groups.
+ */
+
+use std::ptr;
+
+use crate::*;
+
+pub const GROUP    :u8  = 0xFE;   // the first byte of a GroupReq, never that of a Request
+pub const GROUP_MAX:u32 = 1000;   // groups an answer carries at most
+
+#[derive (Debug, Copy,Clone)]
+#[repr (C,packed)]
+pub struct GroupReq {
+	pub kind  : u8     ,  // GROUP
+	pub col   : Column ,
+	pub bucket: u32    ,  // 0: so that there are about N groups
+	pub N     : u32    ,
+	pub tag   : u32    ,
+	pub tbl   : u32       // id of the table
+}
+pub const GREQ_SZ:usize = mem::size_of::<GroupReq> ();
+
+impl GroupReq {
+	pub fn new (tbl:u32, col:Column, bucket:u32, N:u32, tag:u32)->Self {
+		GroupReq { kind: GROUP, col, bucket, N, tag, tbl }
+	}
+
+	pub fn to_bytes (&self)->Vec<u8> {
+		let p=self as *const GroupReq as *const u8;
+		unsafe { slice::from_raw_parts (p,GREQ_SZ) }.to_vec ()
+	}
+
+	pub fn from_bytes (buf:&[u8])->Option<Self> {
+		if buf.len ()!=GREQ_SZ || buf[0]!=GROUP || buf[1]>=Column::NumCol as u8 {
+			return None
+		}
+		Some (unsafe { ptr::read_unaligned (buf.as_ptr () as *const GroupReq) })
+	}
+}
+
+#[derive (Debug, Copy,Clone, PartialEq)]
+pub struct Group {
+	pub key  : Record,  // the lowest value of the bucket, in the column's field
+	pub count: u32
+}
+pub const GROUP_SZ:usize = REC_SZ + mem::size_of::<u32> ();
+
+impl Group {
+	// The groups that follow the header of a response
+	pub fn parse (buf:&[u8])->Vec<Group> {
+		buf.get (RESP_SZ..).unwrap_or_default ().chunks_exact (GROUP_SZ).map (|g| Group {
+			key  : unsafe { ptr::read_unaligned (g.as_ptr () as *const Record) },
+			count: u32::from_ne_bytes (g[REC_SZ..].try_into ().unwrap ())
+		}).collect ()
+	}
+}
+
+impl Table {
+	/* (key, count) for every bucket of the column's values that has any rows, in the order of the index.
+	   bucket: the width of the ranges for id/num, the length of the prefixes for str; 0 and 1 - every value. */
+	pub fn group_by (&self, col:Column, bucket:u32)->Vec<Group> {
+		let ix=&self.fields[col as usize];
+		let rows=self.part (ix, 0, self.tot, &self.expired ());
+		let mut groups=vec![];
+
+		let mut n=0;  // rows before the group
+		while n<self.tot {
+			let first=ix.lookup (n+1).elem.clone ();
+			let (key,last) = bounds (col, ix.coll, &first, bucket);
+			let end=ix.count (&last, true);
+
+			let count=end-n - (rows.hidden (end) - rows.hidden (n));
+			if count>0 {
+				groups.push (Group { key, count });
+			}
+			n = end;
+		}
+
+		groups
+	}
+
+	// The bucket that makes about N groups of the column
+	pub fn bucket_for (&self, col:Column, N:u32)->u32 {
+		let ix=&self.fields[col as usize];
+		if self.tot==0 || N<=1 {
+			return 0
+		}
+
+		if col==Column::Str && ix.coll==Collation::Natural {
+			return SLEN as u32
+		}
+		let gone=self.expired ();
+		if col==Column::Str {
+			// the longest prefix that still keeps the groups within N
+			let rows=self.part (ix, 0, self.tot, &gone);
+			return (1..=SLEN as u32).take_while (|&p| self.groups_upto (col, p, N, &rows)<=N).last ().unwrap_or (1)
+		}
+
+		// the span of the values, the NULLs at either end left out
+		let (_,(lo,hi)) = ix.null_range (self.tot);
+		let vals=self.part (ix, lo, hi, &gone);
+		let (Some (min),Some (max)) = (vals.take (ix, false, 1, 1).pop (), vals.take (ix, true, 1, 1).pop ()) else {
+			return 0
+		};
+		let (min,max) = (ix.val (&min), ix.val (&max));
+		let span=(max-min+1) as u64;
+		cmp::max (1, span.div_ceil (N as u64 - 1)).min (u32::MAX as u64) as u32
+	}
+
+	// Number of the groups of the rows (of the column's index), counted up to limit+1 at most
+	fn groups_upto (&self, col:Column, bucket:u32, limit:u32, rows:&Rows)->u32 {
+		let ix=&self.fields[col as usize];
+		let (mut n, mut groups) = (0, 0);
+		while n<self.tot && groups<=limit {
+			let (_,last) = bounds (col, ix.coll, &ix.lookup (n+1).elem, bucket);
+			let end=ix.count (&last, true);
+			groups += (end-n > rows.hidden (end) - rows.hidden (n)) as u32;
+			n = end;
+		}
+		groups
+	}
+
+	// The answer to a GroupReq, the way it goes over the wire
+	pub fn group_answer (&self, req:GroupReq)->Vec<u8> {
+		let bucket=if req.bucket==0 {self.bucket_for (req.col, req.N)} else {req.bucket};
+		let groups=self.group_by (req.col, bucket);
+		let resp=Response { ok: true, CS: bucket, NS: groups.len () as u32, tag: req.tag };
+
+		let mut data=resp.encode (&[]);
+		for g in groups.iter ().take (GROUP_MAX as usize) {
+			let p=&g.key as *const Record as *const u8;
+			data.extend_from_slice (unsafe { slice::from_raw_parts (p,REC_SZ) });
+			data.extend_from_slice (&g.count.to_ne_bytes ());
+		}
+		data
+	}
+}
+
+/* The key of the bucket a record falls into, and the highest key of the bucket in the index order.
+   The NULLs make a group of their own. */
+fn bounds (col:Column, coll:Collation, r:&Record, bucket:u32)->(Record,Record) {
+	if r.is_null (col) {
+		let key=Record::default ().null (col);
+		return (key, Record { id: u32::MAX, ..key })
+	}
+
+	let (mut key, mut last) = (Record::default (), Record { id: u32::MAX, ..Default::default () });
+	let b=cmp::max (1, bucket) as i64;
+
+	match col {
+		Column::Id  => {
+			let lo=(r.id as i64).div_euclid (b)*b;
+			key .id = lo as u32;
+			last.id = cmp::min (lo+b-1, u32::MAX as i64) as u32;
+		}
+		Column::Num => {
+			let lo=(r.num as i64).div_euclid (b)*b;
+			key .num = cmp::max (lo, i32::MIN as i64) as i32;
+			last.num = cmp::min (lo+b-1, i32::MAX as i64) as i32;
+		}
+		_ => {
+			let p=if bucket==0 || coll==Collation::Natural {SLEN} else {cmp::min (bucket as usize, SLEN)};
+			key .str = [b' ' ;SLEN];
+			last.str = [0xFF;SLEN];
+			key .str[..p].copy_from_slice (&r.str[..p]);
+			last.str[..p].copy_from_slice (&r.str[..p]);
+			if coll==Collation::NoCase {
+				// of the prefixes that fold the same, the lowercase one goes last
+				last.str[..p].make_ascii_lowercase ();
+			}
+		}
+	}
+
+	(key,last)
+}
+
+#[cfg (test)]
+mod tests {
+	use super::*;
+	use std::collections::BTreeMap;
+
+	#[test]
+	fn group_by () {
+		let mut t=Table::new ();
+		for id in 0..2000 {
+			t.add (Record::new (id*3, (id as i32*7919)%2003-1000, ["ab","abc","b","ba","c"][id as usize % 5])).unwrap ();
+		}
+
+		for (col,bucket) in [(Column::Id,0), (Column::Id,100), (Column::Num,1), (Column::Num,7), (Column::Num,u32::MAX),
+		                     (Column::Str,0), (Column::Str,1), (Column::Str,2)] {
+			let mut want=BTreeMap::<(i64,Vec<u8>),u32>::new ();
+			let b=cmp::max (1, bucket) as i64;
+			for r in t.iter () {
+				let k=match col {
+					Column::Id  => ((r.id as i64).div_euclid (b)*b, vec![]),
+					Column::Num => ((r.num as i64).div_euclid (b)*b, vec![]),
+					_           => (0, r.str[..if bucket==0 {SLEN} else {bucket as usize}].to_vec ())
+				};
+				*want.entry (k).or_default () += 1;
+			}
+
+			let got=t.group_by (col, bucket);
+			assert_eq! (got.len (), want.len (), "{col:?} {bucket}");
+			for (g,(k,&n)) in got.iter ().zip (want.iter ()) {
+				assert_eq! (g.count, n);
+				match col {
+					Column::Id  => assert_eq! (g.key.id  as i64, k.0),
+					Column::Num => assert_eq! (g.key.num as i64, cmp::max (k.0, i32::MIN as i64)),
+					_           => assert! (g.key.str.starts_with (&k.1))
+				}
+			}
+		}
+
+		for col in [Column::Id, Column::Num, Column::Str] {
+			let b=t.bucket_for (col, 20);
+			let n=t.group_by (col, b).len ();
+			assert! (n<=20 && (col==Column::Str || n>=10), "{col:?} {b} {n}");
+		}
+
+		let a=t.group_answer (GroupReq::from_bytes (&GroupReq::new (0, Column::Str, 1, 10, 5).to_bytes ()).unwrap ());
+		let r=Response::from_bytes (&a).unwrap ();
+		assert! (r.ok && {r.CS}==1 && {r.NS}==3 && {r.tag}==5);
+		assert_eq! (Group::parse (&a).iter ().map (|g| g.count).collect::<Vec<_>> (), [800,800,400]);
+	}
+
+	#[test]
+	fn nulls () {
+		let mut t=Table::new ();
+		for id in 0..1000 {
+			t.add (Record::new (id, 10000+id as i32, "")).unwrap ();
+		}
+		t.add (Record::new (1000, 0, "").null (Column::Num)).unwrap ();
+
+		// the buckets span the values only, whichever end the NULLs are at; they're a group of their own
+		for nulls in [Nulls::Last, Nulls::First] {
+			t.set_nulls (Column::Num, nulls).unwrap ();
+			let b=t.bucket_for (Column::Num, 20);
+			let groups=t.group_by (Column::Num, b);
+			assert! (groups.len ()>=10 && groups.len ()<=21, "{nulls:?} {b} {}", groups.len ());
+			let null=if nulls==Nulls::First {groups.first ()} else {groups.last ()};
+			assert! (null.is_some_and (|g| g.key.is_null (Column::Num) && g.count==1));
+			assert_eq! (groups.iter ().map (|g| g.count).sum::<u32> (), 1001);
+		}
+
+		t.delete_range (Column::Id, ..Record::new (1000, 0, ""));
+		assert_eq! ((t.bucket_for (Column::Num, 20), t.group_by (Column::Num, 0).len ()), (0, 1));
+	}
+}
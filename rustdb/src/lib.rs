@@ -1,88 +1,287 @@
 /* TODO (v2):
 	-improve API (add Default, Drop, etc., make impl more safe)
 	-make Index generic over an external type (e.g. Record, which also has to be generic).
-	-only allocate the necessary number of levels (not always MAXLVL)
-	-MAXLVL is a function of current number of elements, increased/shrinked depending on it
-	-ncurses client
 	-some sort of integration tests
 	-todos over text
  */
 
 #![allow (non_snake_case)]
+#![allow (clippy::needless_range_loop)]
 
 use core::{
 	array,
 	slice
 };
 use std::{
+	alloc::{self,Layout},
 	cmp,
 	fmt,
+	marker::PhantomData,
 	mem,
+	ops::{Bound,RangeBounds},
 	ptr,
 	str,
 	sync::Arc
 };
 use memoffset::offset_of;
-use rand::{Rng,thread_rng};
+use rand::{Rng,SeedableRng,rngs::StdRng,thread_rng};
 use rayon::prelude::*;
 
+mod aggregate;
+mod cdc;
+mod collate;
+mod csv;
+mod database;
+mod error;
+mod filter;
+mod group;
+mod memory;
+mod query;
+mod snapshot;
+mod ttl;
+mod verify;
+
+pub use aggregate::{Agg,Aggregate};
+pub use cdc::{Change,ChangesReq,Event,CHANGES,CREQ_SZ};
+pub use collate::Collation;
+pub use csv::CSV_NULL;
+pub use database::{Database,Schema,DEFAULT_TABLE};
+pub use error::Error;
+pub use filter::{Filter,Op};
+pub use group::{Group,GroupReq,GROUP,GROUP_MAX,GREQ_SZ,GROUP_SZ};
+pub use memory::Memory;
+pub use query::{Query,Statement,QUERY,QUERY_MAX};
+use filter::Rows;
+
 /* Sample record fields:
 	id : u32 - primary key
 	num: i32
 	s  : &str (represented as [u8;SLEN])
+   and a bitmap of the fields that are NULL (the id never is)
  */
 #[derive (Copy,Clone,Debug,PartialEq,PartialOrd)]
+#[repr (u8)]
 pub enum Column { Id, Num, Str, NumCol }
 
 pub const SLEN:usize=4;        // string length of table's s field
 
-#[derive (Debug, Copy,Clone, Default)]
+#[derive (Debug, Copy,Clone, Default, PartialEq)]
 pub struct Record {
-	pub id : u32,
-	pub num: i32,
-	pub str: [u8;SLEN]
+	pub id   : u32,
+	pub num  : i32,
+	pub str  : [u8;SLEN],
+	pub nulls: u32         // 1<<column for each NULL field
 }
 pub const REC_SZ:usize = mem::size_of::<Record> ();
 
 impl Record {
-	pub fn gen (id:u32)->Self {
+	// The string is cut to SLEN bytes (on a char boundary) and padded with spaces, like SQL's CHAR(n)
+	pub fn new (id:u32, num:i32, s:&str)->Self {
+		let mut n=cmp::min (s.len (), SLEN);
+		while !s.is_char_boundary (n) {
+			n -= 1;
+		}
+
+		let mut str=[b' ';SLEN];
+		str[..n].copy_from_slice (&s.as_bytes ()[..n]);
+
+		Self { id, num, str, nulls: 0 }
+	}
+
+	// The same record with the field of the column NULL (and zeroed)
+	pub fn null (mut self, col:Column)->Self {
+		match col {
+			Column::Id  => self.id  = 0,
+			Column::Num => self.num = 0,
+			_           => self.str = [0;SLEN]
+		}
+		self.nulls |= 1<<col as u32;
+		self
+	}
+
+	pub fn is_null (&self, col:Column)->bool {
+		self.nulls & 1<<col as u32 != 0
+	}
+
+	// The field as it's shown: the value, or NULL
+	pub fn text (&self, col:Column)->String {
+		if self.is_null (col) {
+			return "NULL".to_string ()
+		}
+		match col {
+			Column::Id  => self.id .to_string (),
+			Column::Num => self.num.to_string (),
+			_           => String::from_utf8_lossy (&self.str).to_string ()
+		}
+	}
+
+	// A random record, the same for the same state of the rng (a seeded one, say)
+	pub fn gen (id:u32, rng:&mut impl Rng)->Self {
 		Self {
 			id,
-			num: thread_rng ().gen_range (-1000000..=1000000),
-			str: array::from_fn (|_| thread_rng ().gen_range ('a'..='z') as u8)
+			num: rng.gen_range (-1000000..=1000000),
+			str: array::from_fn (|_| rng.gen_range ('a'..='z') as u8),
+			nulls: 0
 		}
 	}
 }
 
 impl fmt::Display for Record {
 	fn fmt (&self, f:&mut fmt::Formatter)->fmt::Result {
-		let str = str::from_utf8 (&self.str).unwrap ();
-		write! (f, "id={}, num={}, str={str}", self.id, self.num)
+		write! (f, "id={}, num={}, str={}", self.id, self.text (Column::Num), self.text (Column::Str))
+	}
+}
+
+/* How a request tells which page it wants. The key cursors refer to a (sort column value, id) pair,
+   which unlike a screen number keeps pointing at the same place while rows are added and removed.
+   Before/After are in the sort direction, i.e. reversed for backwards sorting. */
+#[derive (Copy,Clone,Debug,PartialEq,PartialOrd)]
+#[repr (u8)]
+pub enum Cursor {
+	Pos,     // screen CS out of NS
+	At,      // starting at the key (inclusive)
+	After,   // the N rows following the key
+	Before,  // the N rows preceding the key
+	NumCur
+}
+
+/* What an index orders the rows by: a column's value, or an expression of it.
+   The indexes on the expressions are only there once created (Table::create_index). */
+#[derive (Copy,Clone,Debug,PartialEq,PartialOrd)]
+#[repr (u8)]
+pub enum Expr {
+	Field,   // the value itself
+	Abs,     // abs(num)
+	Lower,   // str, ASCII case-insensitively
+	NumExpr
+}
+
+// How an index is called: its column, or the expression of it
+fn index_name (col:Column, expr:Expr)->String {
+	let name=["id","num","str"][col as usize];
+	match expr {
+		Expr::Abs   => format! ("abs({name})"),
+		Expr::Lower => format! ("lower({name})"),
+		_           => name.to_string ()
 	}
 }
 
 #[derive (Debug, Copy,Clone)]
-#[repr (packed)]
+#[repr (C,packed)]
 pub struct Request {
-	pub col: Column ,  // column to sort by
-	pub bw : bool   ,  // is sorting backwards?
-	pub N  : u32    ,  // screen height
-	pub CS : u32    ,  // client's Current Screen
-	pub NS : u32       // Number of Screens (from client's perspective)
+	pub col : Column ,  // column to sort by
+	pub bw  : bool   ,  // is sorting backwards?
+	pub sub : bool   ,  // keep pushing this page to the client whenever the table changes
+	pub cur : Cursor ,  // what the page is relative to
+	pub expr: Expr   ,  // of the column, to sort by
+	pub N   : u32    ,  // screen height
+	pub CS  : u32    ,  // client's Current Screen
+	pub NS  : u32    ,  // Number of Screens (from client's perspective)
+	pub tag : u32    ,  // echoed back in the response, to tell the replies (and pushes) apart
+	pub tbl : u32    ,  // id of the table
+	pub key : Record    // for the key cursors: only the sort column and the id matter
 }
 pub const REQ_SZ:usize = mem::size_of::<Request> ();
 
+impl Request {
+	// The first page of N rows by the column, ascending, of the default table: the rest is set with ..Request::new
+	pub fn new (col:Column, N:u32)->Self {
+		Request { col, bw: false, sub: false, cur: Cursor::Pos, expr: Expr::Field, N, CS: 0, NS: 1, tag: 0, tbl: 0, key: Record::default () }
+	}
+
+	// Requests arrive from the network, so the enum and bool bytes are validated before the cast
+	pub fn from_bytes (buf:&[u8])->Option<Self> {
+		if buf.len ()!=REQ_SZ || buf[0]>=Column::NumCol as u8 || buf[1]>1 || buf[2]>1 || buf[3]>=Cursor::NumCur as u8 ||
+		   buf[4]>=Expr::NumExpr as u8 {
+			return None
+		}
+		Some (unsafe { ptr::read_unaligned (buf.as_ptr () as *const Request) })
+	}
+}
+
 #[derive (Debug, Copy,Clone)]
-#[repr (packed)]
+#[repr (C,packed)]
 pub struct Response {
-	pub ok: bool,      // request status
-	pub CS: u32 ,      // Current Screen that is being passed to client
-	pub NS: u32        // Number of Screens (server's version)
+	pub ok : bool,     // request status
+	pub CS : u32 ,     // Current Screen that is being passed to client
+	pub NS : u32 ,     // Number of Screens (server's version)
+	pub tag: u32       // tag of the request this answers
 }
 pub const RESP_SZ:usize = mem::size_of::<Response> ();
 
-#[derive (Debug)]
-enum Dt {U32, I32, STR}  // possible data types for table columns
+impl Response {
+	// The header of a response (the records follow it)
+	pub fn from_bytes (buf:&[u8])->Option<Self> {
+		if buf.len ()<RESP_SZ || buf[0]>1 {
+			return None
+		}
+		Some (unsafe { ptr::read_unaligned (buf.as_ptr () as *const Response) })
+	}
+
+	// The records that follow the header of a response
+	pub fn records (buf:&[u8])->Vec<Record> {
+		buf.get (RESP_SZ..).unwrap_or_default ().chunks_exact (REC_SZ).map (|r| unsafe {
+			ptr::read_unaligned (r.as_ptr () as *const Record)  // not aligned in buf
+		}).collect ()
+	}
+
+	// Serialize the response followed by its records, the way it goes over the wire
+	pub fn encode (&self, recs:&[Arc<Record>])->Vec<u8> {
+		let mut data=Vec::with_capacity (RESP_SZ + REC_SZ*recs.len ());
+
+		let p=self as *const Response as *const u8;
+		data.extend_from_slice (unsafe { slice::from_raw_parts (p,RESP_SZ) });
+
+		for r in recs {
+			let p=&**r as *const Record as *const u8;
+			data.extend_from_slice (unsafe { slice::from_raw_parts (p,REC_SZ) });
+		}
+
+		data
+	}
+}
+
+/* Over a stream transport (TCP) every message, in both directions, is preceded by its length */
+pub const FRAME_HDR:usize = mem::size_of::<u32> ();
+
+/* Rounded x*num/den. Positions and screens are scaled with it exactly: f32 has only 24 bits of mantissa,
+   which isn't enough for the tables of millions of rows */
+pub fn scale (x:u32, num:u32, den:u32)->u32 {
+	let (x,num,den) = (x as u128, num as u128, den as u128);
+	((2*x*num + den) / (2*den)) as u32
+}
+
+/* Position (in the sort direction) of the page for a key cursor, given the number of the rows before
+   the key and up to it (in the forward direction). It's kept within the full pages, so that the client
+   never gets a partial page at either end. */
+fn seek (bw:bool, cur:Cursor, lt:u32, le:u32, tot:u32, N:u32)->u32 {
+	let (lt,le,tot,N) = (lt as i64, le as i64, tot as i64, N as i64);
+
+	let pos = match (cur,bw) {
+		(Cursor::At    , false) => lt+1,
+		(Cursor::After , false) => le+1,
+		(Cursor::Before, false) => lt-N+1,
+		(Cursor::At    , true ) => tot-le+1,
+		(Cursor::After , true ) => tot-lt+1,
+		(Cursor::Before, true ) => tot-le-N+1,
+		_ => unreachable! ()
+	};
+
+	pos.clamp (1, cmp::max (1, tot-N+1)) as u32
+}
+
+#[derive (Debug, Copy,Clone)]
+enum Dt {U32, I32, Str}  // possible data types for table columns
+
+// Where an index puts the NULLs: before or after all the values, in its forward order
+#[derive (Copy,Clone,Debug,Default,PartialEq)]
+pub enum Nulls {
+	First,
+	#[default]
+	Last
+}
+
+const SCAN_MAX:u32 = 1<<20;  // rows a filtered fetch may go through, besides what the index narrows down
 
 /* A table consists of:
  - arbitrary number of records with columns of defined data types
@@ -91,26 +290,85 @@ enum Dt {U32, I32, STR}  // possible data types for table columns
 #[derive (Debug)]
 pub struct Table {
 	fields: [Index; Column::NumCol as usize],
-	tot   : u32
+	exprs : Vec<(Column,Index)>,  // the indexes on the expressions of the columns
+	tot   : u32,
+	ver   : u64,  // bumped on every change
+	cdc   : cdc::Changes,
+	ttl   : ttl::Ttl,
+	cap   : Option<usize>,  // of the memory, in bytes
+	rng   : StdRng          // that the indexes' come from
 }
 
 impl Table {
 	pub fn new ()->Self {
-		Table {
+		Self::with_seed (thread_rng ().gen ())
+	}
+
+	/* A table whose indexes draw the levels of their nodes from rngs seeded from the seed: the same changes
+	   make the same skip-lists, so that a run can be replayed */
+	pub fn with_seed (seed:u64)->Self {
+		let mut t=Table {
 			fields: [
 				// TODO: A lot of code repeat here, need to make it generic over Record type
-				Index::new (offset_of! (Record,id ), Dt::U32),
-				Index::new (offset_of! (Record,num), Dt::I32),
-				Index::new (offset_of! (Record,str), Dt::STR)
+				Index::new (Column::Id , offset_of! (Record,id ), Dt::U32, true ),
+				Index::new (Column::Num, offset_of! (Record,num), Dt::I32, true ),
+				Index::new (Column::Str, offset_of! (Record,str), Dt::Str, false)
 			],
-			tot   : 0
+			exprs : vec![],
+			tot   : 0,
+			ver   : 0,
+			cdc   : cdc::Changes::new (),
+			ttl   : Default::default (),
+			cap   : None,
+			rng   : StdRng::seed_from_u64 (seed)
+		};
+		t.reseed (seed);
+		t
+	}
+
+	// The indexes draw the levels anew from the seed, those of the nodes to come
+	pub fn reseed (&mut self, seed:u64) {
+		self.rng = StdRng::seed_from_u64 (seed);
+		for ix in self.fields.iter_mut ().chain (self.exprs.iter_mut ().map (|(_,ix)| ix)) {
+			ix.rng = fork (&mut self.rng);
 		}
 	}
 
-	pub fn add (&mut self, rec:Record)->Result<(),()> {
-		// id is a primary key, has to be unique
+	pub fn add (&mut self, rec:Record)->Result<(),Error> {
+		self.room ()?;
+		let r=self.insert (rec)?;
+		self.cdc.publish (Change::Insert (r));
+		Ok (())
+	}
+
+	pub fn rm (&mut self, id:u32)->Result<(),Error> {  // we remove only by the primary key
+		let r=self.take (id)?;
+		self.ttl.set (id, None);
+		self.cdc.publish (Change::Delete (r));
+		Ok (())
+	}
+
+	pub fn upd (&mut self, new:Record)->Result<(),Error> {
+		// TODO: do search instead of rm+upd to save (rm+add)'ing of id field
+		//   (make search return mutable ref to the Record)
+		// refused before the row is taken out, not to lose it: the id is the only one insert checks that can fail then
+		if new.is_null (Column::Id) {
+			return Err (Error::NullId)
+		}
+		let before=self.take (new.id)?;
+		let after=self.insert (new)?;
+		self.cdc.publish (Change::Update {before, after});
+
+		Ok (())
+	}
+
+	fn insert (&mut self, rec:Record)->Result<Arc<Record>,Error> {
+		// id is a primary key, has to be unique (and not NULL)
+		if rec.is_null (Column::Id) {
+			return Err (Error::NullId)
+		}
 		if self.fields[Column::Id as usize].search (&rec).is_some () {
-			return Err (())
+			return Err (Error::Exists)
 		}
 
 		// Record needs to be boxed and freed only when all indexes deleted
@@ -118,15 +376,19 @@ impl Table {
 		self.fields.par_iter_mut ().for_each (|x| {
 			x.insert (arc.clone ());
 		});
+		self.exprs.par_iter_mut ().for_each (|(_,x)| {
+			x.insert (arc.clone ());
+		});
 
 		self.tot += 1;
+		self.ver += 1;
 
-		Ok (())
+		Ok (arc)
 	}
 
-	pub fn rm (&mut self, id:u32)->Result<(),()> {  // we remove only by the primary key
+	fn take (&mut self, id:u32)->Result<Arc<Record>,Error> {
 		let rec=self.fields[Column::Id as usize]
-		       .delete (&Record {id,..Default::default ()},false).ok_or (())?;
+		       .delete (&Record {id,..Default::default ()},false).ok_or (Error::NotFound)?;
 
 		self.fields.par_iter_mut ().skip (1).for_each (|x| {
 			// Delete from other columns, using received Record's values (with the same address)
@@ -135,94 +397,362 @@ impl Table {
 
 			// The contained Arc value is gonna be dropped, and the count decremented
 		});
+		self.exprs.par_iter_mut ().for_each (|(_,x)| {
+			x.delete (&rec, true).expect ("Expression index returned none, whilst it shouldn't");
+		});
 
 		self.tot -= 1;
+		self.ver += 1;
+
+		Ok (rec)
+	}
+
+	/* Removes the rows that pass the filter, gathered from the index that narrows them down the most.
+	   The number of the rows removed, the expired ones not counted (see remove). */
+	pub fn delete_where (&mut self, filter:&Filter)->u32 {
+		let (by,(a,b)) = self.narrowest (filter);
+		let recs=self.fields[by as usize].iter_from (a).take ((b-a) as usize)
+		         .filter (|r| filter.eval (r)).cloned ().collect ();
+
+		self.remove (recs)
+	}
+
+	/* Removes the rows with the column's field within the range (in the order of its index, NULLs never are).
+	   As with the filters, only that field of the bounds matters. The number of the rows removed,
+	   the expired ones not counted (see remove). */
+	pub fn delete_range (&mut self, col:Column, range:impl RangeBounds<Record>)->u32 {
+		let ix=&self.fields[col as usize];
+		let key=|v:&Record, last:bool| {
+			let mut k=Record { nulls: 0, ..*v };
+			if col!=Column::Id {
+				k.id = if last {u32::MAX} else {0};
+			}
+			k
+		};
+
+		let (_,(mut a, mut b)) = ix.null_range (self.tot);
+		match range.start_bound () {
+			Bound::Included (v) => a = cmp::max (a, ix.count (&key (v,false), false)),
+			Bound::Excluded (v) => a = cmp::max (a, ix.count (&key (v,true ), true )),
+			Bound::Unbounded    => {}
+		}
+		match range.end_bound () {
+			Bound::Included (v) => b = cmp::min (b, ix.count (&key (v,true ), true )),
+			Bound::Excluded (v) => b = cmp::min (b, ix.count (&key (v,false), false)),
+			Bound::Unbounded    => {}
+		}
+		let recs=ix.iter_from (a).take (b.saturating_sub (a) as usize).cloned ().collect ();
+
+		self.remove (recs)
+	}
+
+	/* Takes the records out of every index, the indexes in parallel rather than a record after another.
+	   The number of them, less those expired: they go along, but were no longer there as far as the rest of the calls go. */
+	fn remove (&mut self, recs:Vec<Arc<Record>>)->u32 {
+		let gone=self.expired ();
+		let seen=recs.iter ().filter (|r| !gone.has (r)).count () as u32;
+		let del=|ix:&mut Index| for r in &recs {
+			ix.delete (r, true).expect ("Index returned none, whilst it shouldn't");
+		};
+		self.fields.par_iter_mut ().for_each (del);
+		self.exprs.par_iter_mut ().for_each (|(_,ix)| del (ix));
+
+		let n=recs.len () as u32;
+		self.tot -= n;
+		self.ver += (n>0) as u64;
+		for r in recs {
+			self.ttl.set (r.id, None);
+			self.cdc.publish (Change::Delete (r));
+		}
+		seen
+	}
+
+	/* An index on an expression of a column, filled with the rows there are and kept up to date from then on.
+	   Refused if it's there already, or if the expression doesn't apply to the column's type. */
+	pub fn create_index (&mut self, col:Column, expr:Expr)->Result<(),Error> {
+		if col>=Column::NumCol || self.index (col,expr).is_some () {
+			return Err (Error::Invalid)
+		}
+		let base=&self.fields[col as usize];
+		match (&base.dt, expr) {
+			(Dt::I32, Expr::Abs) | (Dt::Str, Expr::Lower) => {}  // abs of an unsigned one would be the column's own index
+			_ => return Err (Error::Invalid)
+		}
+
+		let mut ix=Index::new (col, base.off, base.dt, false);
+		ix.expr  = expr;
+		ix.nulls = base.nulls;
+		ix.rng   = fork (&mut self.rng);
+		for r in base.iter () {
+			ix.insert (r.clone ());
+		}
+		self.exprs.push ((col,ix));
 
 		Ok (())
 	}
 
-	pub fn upd (&mut self, new:Record)->Result<(),()> {
-		// TODO: do search instead of rm+upd to save (rm+add)'ing of id field
-		//   (make search return mutable ref to the Record)
-		self.rm (new.id)?;
-		self.add (new)?;
+	// Where the index of the column puts the NULLs. The index is rebuilt.
+	pub fn set_nulls (&mut self, col:Column, nulls:Nulls)->Result<(),Error> {
+		self.rebuild (col, |ix| ix.nulls = nulls)
+	}
+
+	pub fn nulls (&self, col:Column)->Nulls {
+		self.fields[col as usize].nulls
+	}
+
+	// The index of the column made anew, set up by f and filled with the rows there are
+	fn rebuild (&mut self, col:Column, f:impl FnOnce (&mut Index))->Result<(),Error> {
+		let old=self.fields.get (col as usize).ok_or (Error::Invalid)?;
+		let mut ix=Index::new (col, old.off, old.dt, old.sums);
+		(ix.coll, ix.nulls) = (old.coll, old.nulls);
+		ix.rng = fork (&mut self.rng);
+		f (&mut ix);
+
+		for r in old.iter () {
+			ix.insert (r.clone ());
+		}
+		self.fields[col as usize] = ix;
+		self.ver += 1;
 
 		Ok (())
 	}
 
+	// The index that orders by the expression of the column, if there's one
+	fn index (&self, col:Column, expr:Expr)->Option<&Index> {
+		if expr==Expr::Field {
+			return self.fields.get (col as usize)
+		}
+		self.exprs.iter ().find (|(c,ix)| *c==col && ix.expr==expr).map (|(_,ix)| ix)
+	}
+
+	// The columns' indexes, then those on the expressions
+	fn indexes (&self)->impl Iterator<Item=&Index> {
+		self.fields.iter ().chain (self.exprs.iter ().map (|(_,ix)| ix))
+	}
+
 	pub fn fetch (&self, req:Request)->(Response,Vec<u8>) {
+		let (resp,recs)=self.fetch_recs (req);
+		let mut data=resp.encode (&recs);
+
+		(resp, data.split_off (RESP_SZ))
+	}
+
+	/* Same as fetch, but only grabs references to the records, so that a caller can release
+	   the table lock before serializing them */
+	pub fn fetch_recs (&self, req:Request)->(Response,Vec<Arc<Record>>) {
+		self.fetch_filtered (req, None)
+	}
+
+	/* Pages over the records that pass the filter, the positions and the totals being among those.
+	   Refused if the filter would need a scan of more than SCAN_MAX rows. */
+	pub fn fetch_filtered (&self, req:Request, filter:Option<&Filter>)->(Response,Vec<Arc<Record>>) {
 		let mut resp=Response {
-			ok: false,
-			CS: 0,
-			NS: 1
+			ok : false,
+			CS : 0,
+			NS : 1,
+			tag: req.tag
 		};
 		let mut data=Vec::new ();
 
-		if req.col>=Column::NumCol || req.N==0 || req.NS==0 || req.CS>=req.NS {
+		if req.col>=Column::NumCol || req.cur>=Cursor::NumCur || req.N==0 ||
+		   (req.cur==Cursor::Pos && (req.NS==0 || req.CS>=req.NS)) {
 			return (resp,data)
 		}
+		let Some (ix)=self.index (req.col, req.expr) else {
+			return (resp,data)
+		};
+		let Some (rows)=self.rows (ix, req.col, filter, &self.expired ()) else {
+			return (resp,data)
+		};
 		resp.ok=true;
 
-		if self.tot==0 {
+		let tot=rows.len ();
+		if tot==0 {
 			return (resp,data)
 		}
 
 		/* Fill response */
-		let mut pos = if req.NS>1 && self.tot>req.N {
-			(1.0 + req.CS as f32 * (self.tot-req.N) as f32
-			                     / (req.NS-1) as f32)
-			.round () as u32
-		}
-		else {
-			1
+		let pos = match req.cur {
+			Cursor::Pos => if req.NS>1 && tot>req.N {
+				1 + scale (req.CS, tot-req.N, req.NS-1)
+			}
+			else {
+				1
+			},
+			cur => {
+				let key={req.key};
+				seek (req.bw, cur, rows.count (ix,&key,false), rows.count (ix,&key,true), tot, req.N)
+			}
 		};
 		resp.CS=pos-1;
 
-		if self.tot>req.N {
-			resp.NS = self.tot-req.N+1;
+		if tot>req.N {
+			resp.NS = tot-req.N+1;
 		}
 
 		/* Fill data vector */
-		if req.bw {
-			pos = self.tot-pos + 1
-		}
-		assert! (pos!=0 && pos<=self.tot);
+		data = rows.take (ix, req.bw, pos, req.N);
 
-		let min=cmp::min (req.N, self.tot);                      // if total<req, send total
-		let mut cur=self.fields[req.col as usize].lookup (pos);
+		(resp,data)
+	}
 
-		for _ in 0..min {
-			let r = &*cur.elem as *const Record as *const u8;
-			let sl=unsafe {
-				slice::from_raw_parts (r,REC_SZ)
-			}.to_vec ();
+	// The column whose index narrows the filter's rows down the most, and their range in it
+	fn narrowest (&self, f:&Filter)->(Column,(u32,u32)) {
+		[Column::Id, Column::Num, Column::Str].into_iter ()
+		.map (|c| { let (a,b,_)=f.range (c, &self.fields[c as usize], self.tot); (c,(a,b)) })
+		.min_by_key (|&(_,(a,b))| b-a).unwrap ()
+	}
 
-			data.extend (sl);
+	/* The rows that pass the filter, in the order of the column's index. The filter's range on the column comes
+	   from the index, whatever else the filter has needs a scan of that range: none if it's too long.
+	   The expired rows (as found by Table::expired) are left out. */
+	fn rows<'a> (&self, ix:&'a Index, col:Column, filter:Option<&Filter>, gone:&ttl::Gone)->Option<Rows<'a>> {
+		Some (match filter.map (|f| (f, f.range (col, ix, self.tot))) {
+			None                                => self.part (ix, 0, self.tot, gone),
+			Some ((_,(a,b,true)))               => self.part (ix, a, b, gone),
+			Some ((f,(a,b,_))) if b-a<=SCAN_MAX => Rows::Scan (ix.iter_from (a).take ((b-a) as usize)
+			                                                     .filter (|r| f.eval (r) && !gone.has (r)).collect ()),
+			Some (_)                            => return None
+		})
+	}
 
-			cur = unsafe { &*(if req.bw {cur.prev.0} else {cur.next[0].0}) };
+	// The positions a..b of the index, less the expired rows
+	fn part (&self, ix:&Index, a:u32, b:u32, gone:&ttl::Gone)->Rows<'static> {
+		if gone.recs.is_empty () {
+			return Rows::Range (a,b)
 		}
-
-		(resp,data)
+		let mut h:Vec<u32> = gone.recs.iter ().map (|r| ix.count (r,false)).filter (|p| (a..b).contains (p)).collect ();
+		h.sort_unstable ();
+		Rows::Hide (a,b,h)
 	}
 
+	// The rows there are, less those expired (counted off the expiry order, without finding them)
 	pub fn tot (&self)->u32 {
-		self.tot
+		self.tot - self.ttl.expired (ttl::now ()).count () as u32
 	}
+
+	// Changes whenever the table does
+	pub fn ver (&self)->u64 {
+		self.ver
+	}
+
+	// Records in the order of the primary key
+	pub fn iter (&self)->impl Iterator<Item=&Record> {
+		self.fields[Column::Id as usize].iter ().map (|r| &**r)
+	}
+}
+
+// An rng of an index, from the table's
+fn fork (rng:&mut StdRng)->StdRng {
+	StdRng::seed_from_u64 (rng.gen ())
 }
 
-// TODO: impl Drop for Table
+impl Default for Table {
+	fn default ()->Self {
+		Self::new ()
+	}
+}
 
 /* An Index (Skip-list) with fingers and double-link at the 0th level */
-const MAXLVL:usize = 24;   // absolute maximum level of a skip-list (i.e. totalmax = 25)
+const MAXLVL:usize = 32;   // absolute maximum level of a skip-list (i.e. totalmax = 33), that of u32::MAX elements
 const P     :f32   = 0.5;  // probability of the node propagation to the next level
 
+/* A node is a single allocation: the header below, then the level arrays, as long as the node's level goes
+	(the head's are MAXLVL+1 long):
+	next: [*mut Node; n]
+	sums: [i64; n]  - the sums of the values that the fingers span, if the index keeps them
+	fing: [u32; n]
+ */
 #[derive (Debug)]
+#[repr (C)]
 struct Node {
 	elem: Arc<Record>,
-	next: Vec<Link>,
-	fing: Vec<u32>,
-	prev: Link
+	prev: Link,
+	n   : u8,    // number of levels
+	sums: bool
+}
+
+// Level accessors, so that the traversals never autoref through a raw `*mut Node`
+impl Node {
+	// A node of n levels, its links null and its fingers (and sums) 0
+	fn alloc (elem:Arc<Record>, n:usize, sums:bool)->*mut Node {
+		let layout=Node::layout (n,sums);
+		unsafe {
+			let node=alloc::alloc_zeroed (layout) as *mut Node;
+			if node.is_null () {
+				alloc::handle_alloc_error (layout)
+			}
+			ptr::write (node, Node { elem, prev: Link (ptr::null_mut ()), n: n as u8, sums });
+			node
+		}
+	}
+
+	// Frees a node made by alloc, its record back
+	unsafe fn free (node:*mut Node)->Arc<Record> {
+		let layout=Node::layout ((*node).n as usize, (*node).sums);
+		let elem=ptr::read (&(*node).elem);
+		alloc::dealloc (node as *mut u8, layout);
+		elem
+	}
+
+	fn layout (n:usize, sums:bool)->Layout {
+		let lvl=mem::size_of::<*mut Node> () + mem::size_of::<u32> () + if sums {mem::size_of::<i64> ()} else {0};
+		Layout::from_size_align (mem::size_of::<Node> () + n*lvl, mem::align_of::<Node> ()).unwrap ().pad_to_align ()
+	}
+
+	// Where the array of the kind of level data starts (the header's size keeps them aligned)
+	fn arr<T> (&self, at:usize)->*mut T {
+		let n=self.n as usize;
+		let off=mem::size_of::<Node> () + match at {
+			NEXT => 0,
+			SUMS => n*mem::size_of::<*mut Node> (),
+			_    => n*mem::size_of::<*mut Node> () + if self.sums {n*mem::size_of::<i64> ()} else {0}
+		};
+		unsafe { (self as *const Node as *mut u8).add (off) as *mut T }
+	}
+
+	fn lvls (&self)->usize {
+		self.n as usize
+	}
+
+	fn nxt (&self, l:usize)->*mut Node {
+		debug_assert! (l<self.lvls ());
+		unsafe { *self.arr::<*mut Node> (NEXT).add (l) }
+	}
+
+	fn set_nxt (&mut self, l:usize, n:*mut Node) {
+		debug_assert! (l<self.lvls ());
+		unsafe { *self.arr::<*mut Node> (NEXT).add (l) = n; }
+	}
+
+	fn fng (&self, l:usize)->u32 {
+		debug_assert! (l<self.lvls ());
+		unsafe { *self.arr::<u32> (FING).add (l) }
+	}
+
+	fn set_fng (&mut self, l:usize, f:u32) {
+		debug_assert! (l<self.lvls ());
+		unsafe { *self.arr::<u32> (FING).add (l) = f; }
+	}
+
+	fn sum (&self, l:usize)->i64 {
+		debug_assert! (self.sums && l<self.lvls ());
+		unsafe { *self.arr::<i64> (SUMS).add (l) }
+	}
+
+	fn set_sum (&mut self, l:usize, s:i64) {
+		debug_assert! (self.sums && l<self.lvls ());
+		unsafe { *self.arr::<i64> (SUMS).add (l) = s; }
+	}
+
+	// Bytes the node takes, with its level arrays (not the record, which the indexes share)
+	fn size (&self)->usize {
+		Node::layout (self.lvls (), self.sums).size ()
+	}
 }
+const NEXT:usize = 0;  // the level arrays of a node
+const SUMS:usize = 1;
+const FING:usize = 2;
 
 #[derive(Debug, Clone)]
 struct Link (*mut Node);
@@ -231,29 +761,69 @@ unsafe impl Sync for Link {}
 
 #[derive (Debug)]
 struct Index {
-	head: Link ,
-	l   : usize,  // total #levels (inc.0)
-	off : usize,  // field offset from the start
-	dt  : Dt      // datatype of the field
+	head : Link     ,
+	l    : usize    ,  // total #levels (inc.0)
+	col  : Column   ,  // of the field
+	off  : usize    ,  // field offset from the start
+	dt   : Dt       ,  // datatype of the field
+	expr : Expr     ,  // of the field, which the index orders by
+	coll : Collation,  // of a str field
+	nulls: Nulls    ,
+	sums : bool     ,  // keep the sums of the (numeric) field next to the fingers
+	mem  : usize    ,  // bytes of the nodes, the head's included
+	len  : u32      ,  // elements
+	rng  : StdRng      // of the levels
 }
 unsafe impl Send for Index {}
 
 impl Index {
-	fn new (off:usize, dt:Dt)->Self {
+	fn new (col:Column, off:usize, dt:Dt, sums:bool)->Self {
+		let head=Node::alloc (Default::default (), MAXLVL+1, sums);
 		Self {
-			head: Link (Box::into_raw (Box::new (Node {
-				elem: Default::default (),
-				next: vec![Link (ptr::null_mut ()); MAXLVL+1],
-				fing: vec![0u32                   ; MAXLVL+1],
-				prev: Link (ptr::null_mut ())
-			}))),
-			l   : 0,
+			mem  : unsafe { (*head).size () },
+			len  : 0,
+			rng  : StdRng::seed_from_u64 (0),  // a table gives it one of its own
+			head : Link (head),
+			l    : 0,
+			col,
 			off,
-			dt
+			dt,
+			expr : Expr::Field,
+			coll : Collation::Binary,
+			nulls: Nulls::Last,
+			sums
 		}
 	}
 
+	// The field as a number, for the sums (a NULL adds nothing)
+	fn val (&self, r:&Record)->i64 {
+		if r.is_null (self.col) {
+			return 0
+		}
+		let r=r as *const Record as *const u8;
+
+		unsafe {
+			match self.dt {
+				Dt::U32=> *(r.add (self.off) as *const u32) as i64,
+				Dt::I32=> *(r.add (self.off) as *const i32) as i64,
+				Dt::Str=> 0
+			}
+		}
+	}
+
+	/* Records are ordered by (field, id): equal fields don't make equal keys, so that any record
+	   (and any position between two records) can be pointed at by a key */
 	fn comp (&self, a:&Record, b:&Record)->cmp::Ordering {
+		self.comp_field (a,b).then (a.id.cmp (&b.id))
+	}
+
+	fn comp_field (&self, a:&Record, b:&Record)->cmp::Ordering {
+		// the NULLs are equal to each other, and go at either end
+		let (x,y) = (a.is_null (self.col), b.is_null (self.col));
+		if x || y {
+			return if self.nulls==Nulls::First {y.cmp (&x)} else {x.cmp (&y)}
+		}
+
 		let a=a as *const Record as *const u8;
 		let b=b as *const Record as *const u8;
 
@@ -267,12 +837,18 @@ impl Index {
 				Dt::I32=> {
 					let a= *(a.add (self.off) as *const i32);
 					let b= *(b.add (self.off) as *const i32);
-					a.cmp (&b)
+					match self.expr {
+						Expr::Abs=> a.unsigned_abs ().cmp (&b.unsigned_abs ()),
+						_        => a.cmp (&b)
+					}
 				}
-				Dt::STR=> {
-					let a=str::from_utf8 (&slice::from_raw_parts (a.add (self.off),SLEN)).unwrap ();
-					let b=str::from_utf8 (&slice::from_raw_parts (b.add (self.off),SLEN)).unwrap ();
-					a.cmp (&b)
+				Dt::Str=> {
+					let a=slice::from_raw_parts (a.add (self.off),SLEN);
+					let b=slice::from_raw_parts (b.add (self.off),SLEN);
+					match self.expr {
+						Expr::Lower=> a.iter ().map (u8::to_ascii_lowercase).cmp (b.iter ().map (u8::to_ascii_lowercase)),
+						_          => self.coll.compare (a,b)
+					}
 				}
 			}
 		}
@@ -283,13 +859,13 @@ impl Index {
 
 		unsafe {
 			for l in (0..self.l).rev () {
-				while !(*cur).next[l].0.is_null () && self.comp (elem, &(*(*cur).next[l].0).elem).is_gt () {
-					cur = (*cur).next[l].0;
+				while !(*cur).nxt (l).is_null () && self.comp (elem, &(*(*cur).nxt (l)).elem).is_gt () {
+					cur = (*cur).nxt (l);
 				}
 			}
 
-			if !(*cur).next[0].0.is_null () && self.comp (elem, &(*(*cur).next[0].0).elem).is_eq () {
-				Some (&(*(*cur).next[0].0))
+			if !(*cur).nxt (0).is_null () && self.comp (elem, &(*(*cur).nxt (0)).elem).is_eq () {
+				Some (&(*(*cur).nxt (0)))
 			}
 			else {
 				None
@@ -303,52 +879,131 @@ impl Index {
 
 		unsafe {
 			for l in (0..self.l).rev () {
-				while !(*cur).next[l].0.is_null () && n > s+(*cur).fing[l] {
-					s += (*cur).fing[l];
-					cur = (*cur).next[l].0;
+				while !(*cur).nxt (l).is_null () && n > s+(*cur).fng (l) {
+					s += (*cur).fng (l);
+					cur = (*cur).nxt (l);
 				}
 			}
-			assert! (!(*cur).next[0].0.is_null ());
+			assert! (!(*cur).nxt (0).is_null ());
 
-			&(*(*cur).next[0].0)
+			&(*(*cur).nxt (0))
+		}
+	}
+
+	// Number of elements before the key (or up to it, inclusive)
+	fn count (&self, key:&Record, incl:bool)->u32 {
+		let mut cur = self.head.0;
+		let mut s:u32=0;
+
+		unsafe {
+			for l in (0..self.l).rev () {
+				while !(*cur).nxt (l).is_null () && {
+					let c=self.comp (key, &(*(*cur).nxt (l)).elem);
+					c.is_gt () || (incl && c.is_eq ())
+				} {
+					s += (*cur).fng (l);
+					cur = (*cur).nxt (l);
+				}
+			}
+		}
+
+		s
+	}
+
+	// Positions a..b of the NULLs (0-based, b exclusive), and of the values, out of tot elements
+	fn null_range (&self, tot:u32)->((u32,u32),(u32,u32)) {
+		let key=Record::default ().null (self.col);
+		let (a,b) = (self.count (&key, false), self.count (&Record { id: u32::MAX, ..key }, true));
+
+		((a,b), if a==0 {(b,tot)} else {(0,a)})
+	}
+
+	// Sum of the first n elements' fields, from the sums next to the fingers (the index has to keep them)
+	fn sum (&self, n:u32)->i64 {
+		assert! (self.sums);
+		let mut cur = self.head.0;
+		let mut s:u32=0;
+		let mut sum:i64=0;
+
+		unsafe {
+			for l in (0..self.l).rev () {
+				while !(*cur).nxt (l).is_null () && n >= s+(*cur).fng (l) {
+					s   += (*cur).fng (l);
+					sum += (*cur).sum (l);
+					cur = (*cur).nxt (l);
+				}
+			}
+		}
+
+		sum
+	}
+
+	// Walk the 0th level from the first element on
+	fn iter (&self)->Iter<'_> {
+		self.iter_from (0)
+	}
+
+	// Elements from the one after the first n on
+	fn iter_from (&self, n:u32)->Iter<'_> {
+		let mut cur = self.head.0;
+		let mut s:u32=0;
+
+		unsafe {
+			for l in (0..self.l).rev () {
+				while !(*cur).nxt (l).is_null () && n >= s+(*cur).fng (l) {
+					s += (*cur).fng (l);
+					cur = (*cur).nxt (l);
+				}
+			}
+
+			Iter {
+				cur: (*cur).nxt (0),
+				_ix: PhantomData
+			}
 		}
 	}
 
 	fn insert (&mut self, elem:Arc<Record>) {
-		let node = Box::into_raw (Box::new (Node {
-			elem,
-			next: vec![Link (ptr::null_mut ()); MAXLVL+1],
-			fing: vec![0u32                   ; MAXLVL+1],
-			prev: Link (ptr::null_mut ())
-		}));
+		let v = if self.sums {self.val (&elem)} else {0};
+
+		// Randomly determine the maxlevel of the current node, the levels it's allocated with
+		let top=top_lvl (self.len);
+		let rval=self.rng.gen_range (0..=LIMS[top]);
+		let maxlvl=get_maxlvl (rval, top);
+		self.len += 1;
+		let node=Node::alloc (elem, maxlvl+1, self.sums);
+		self.mem += unsafe { (*node).size () };
 
 		let mut cur = self.head.0;
-		let mut prv = vec![cur;  MAXLVL+1];  // bread-crumbs of our visit per level
-		let mut d   = vec![0u32; MAXLVL+1];  // distances from the previous nodes per level
-		let mut f   = vec![0u32; MAXLVL+1];  // fingers per level
+		let mut prv = [cur;  MAXLVL+1];  // bread-crumbs of our visit per level
+		let mut d   = [0u32; MAXLVL+1];  // distances from the previous nodes per level
+		let mut f   = [0u32; MAXLVL+1];  // fingers per level
+		let mut ds  = [0i64; MAXLVL+1];  // the same for the sums
+		let mut fs  = [0i64; MAXLVL+1];
 
 		// Find a place where to insert
 		for l in (0..self.l).rev () {
 			unsafe {
-				while !(*cur).next[l].0.is_null () && self.comp (&(*node).elem, &(*(*cur).next[l].0).elem).is_gt () {
-					d[l] += (*cur).fing[l];
-					cur = (*cur).next[l].0;
+				while !(*cur).nxt (l).is_null () && self.comp (&(*node).elem, &(*(*cur).nxt (l)).elem).is_gt () {
+					d[l] += (*cur).fng (l);
+					if self.sums {
+						ds[l] = ds[l].wrapping_add ((*cur).sum (l));
+					}
+					cur = (*cur).nxt (l);
 				}
 			}
 			prv[l] = cur;
 		}
 
-		// Randomly determine the maxlevel of the current node
-		let rval=thread_rng ().gen_range (0..=LIMS[MAXLVL]);
-		let maxlvl=get_maxlvl (rval);
-		if self.l <= maxlvl {          // maxlvl can be [0..MAXLVL]
-			self.l = maxlvl+1;         // total number of levels (can be [1..MAXLVL+1])
+		if self.l <= maxlvl {          // maxlvl can be [0..top]
+			self.l = maxlvl+1;         // total number of levels (can be [1..top+1])
 		}
 
 		// Update fingers
 		for l in 1..self.l {
 			for k in 0..l {
 				f[l] += d[k];
+				fs[l] = fs[l].wrapping_add (ds[k]);
 			}
 		}
 
@@ -356,19 +1011,26 @@ impl Index {
 		unsafe {
 			for l in 0..self.l {
 				if l<=maxlvl {
-					(*node).next[l].0 = (*prv[l]).next[l].0;
-					(*prv[l]).next[l].0 = node;
+					(*node).set_nxt (l, (*prv[l]).nxt (l));
+					(*prv[l]).set_nxt (l, node);
 
 					// when maxlvl is new highest wrap-arounds are possible
-					(*node).fing[l] = (*prv[l]).fing[l].wrapping_sub (f[l]);
-					(*prv[l]).fing[l] = f[l]+1;
+					(*node).set_fng (l, (*prv[l]).fng (l).wrapping_sub (f[l]));
+					(*prv[l]).set_fng (l, f[l]+1);
+					if self.sums {
+						(*node).set_sum (l, (*prv[l]).sum (l).wrapping_sub (fs[l]));
+						(*prv[l]).set_sum (l, fs[l].wrapping_add (v));
+					}
 				}
 				else {
-					(*prv[l]).fing[l] = (*prv[l]).fing[l].wrapping_add (1);
+					(*prv[l]).set_fng (l, (*prv[l]).fng (l).wrapping_add (1));
+					if self.sums {
+						(*prv[l]).set_sum (l, (*prv[l]).sum (l).wrapping_add (v));
+					}
 				}
 			}
-			if !((*node).next[0].0).is_null () {
-				(*(*node).next[0].0).prev.0 = node;
+			if !((*node).nxt (0)).is_null () {
+				(*(*node).nxt (0)).prev.0 = node;
 			}
 			(*node).prev.0 = prv[0];
 		}
@@ -376,54 +1038,63 @@ impl Index {
 
 	fn delete (&mut self, elem:&Record, same:bool)->Option<Arc<Record>> {
 		let mut cur = self.head.0;
-		let mut prv = vec![cur; MAXLVL+1];  // bread-crumbs of our visit per level
+		let mut prv = [cur; MAXLVL+1];  // bread-crumbs of our visit per level
 
 		for l in (0..self.l).rev () {
 			unsafe {
-				while !(*cur).next[l].0.is_null () && self.comp (elem, &(*(*cur).next[l].0).elem).is_gt () {
-					cur = (*cur).next[l].0;
+				while !(*cur).nxt (l).is_null () && self.comp (elem, &(*(*cur).nxt (l)).elem).is_gt () {
+					cur = (*cur).nxt (l);
 				}
 			}
 			prv[l] = cur;
 		}
 
 		unsafe {
-			if !(*cur).next[0].0.is_null () && self.comp (elem, &(*(*cur).next[0].0).elem).is_eq () {
+			if !(*cur).nxt (0).is_null () && self.comp (elem, &(*(*cur).nxt (0)).elem).is_eq () {
 				if same {
 					while {
-						let a1=&*(*(*cur).next[0].0).elem as *const Record;
+						let a1=&*(*(*cur).nxt (0)).elem as *const Record;
 						let a2=elem as *const Record;
 						a1 != a2
 					} {
-						cur = (*cur).next[0].0;
+						cur = (*cur).nxt (0);
 						assert! (!cur.is_null ());
 
 						for l in (0..self.l).rev () {
-							if (*prv[l]).next[l].0==cur {
+							if (*prv[l]).nxt (l)==cur {
 								prv[l] = cur;
 							}
 						}
 					}
 				}
 
-				let tmp = (*cur).next[0].0;
+				let tmp = (*cur).nxt (0);
+				let v = if self.sums {self.val (&(*tmp).elem)} else {0};
 				for l in 0..self.l {
-					if (*prv[l]).next[l].0==tmp {
-						(*prv[l]).next[l].0 = (*tmp).next[l].0;
+					// above its level the node spans nothing, only itself is taken out of the finger over it
+					let ours=l<(*tmp).lvls ();
+					debug_assert_eq! ((*prv[l]).nxt (l)==tmp, ours);
+					if ours {
+						(*prv[l]).set_nxt (l, (*tmp).nxt (l));
+					}
+					let f=if ours {(*tmp).fng (l)} else {0};
+					(*prv[l]).set_fng (l, (*prv[l]).fng (l).wrapping_add (f.wrapping_sub (1)));
+					if self.sums {
+						let s=if ours {(*tmp).sum (l)} else {0};
+						(*prv[l]).set_sum (l, (*prv[l]).sum (l).wrapping_add (s.wrapping_sub (v)));
 					}
-					(*prv[l]).fing[l] = (*prv[l]).fing[l]
-					                  .wrapping_add ((*tmp).fing[l].wrapping_sub (1));
 
-					if (*self.head.0).next[l].0.is_null () {
+					if (*self.head.0).nxt (l).is_null () {
 						self.l -= 1;
 					}
 				}
-				if !(*tmp).next[0].0.is_null () {
-					(*(*tmp).next[0].0).prev.0 = (*tmp).prev.0;
+				if !(*tmp).nxt (0).is_null () {
+					(*(*tmp).nxt (0)).prev.0 = (*tmp).prev.0;
 				}
 
-				let b=Box::from_raw (tmp);
-				Some (b.elem)
+				self.mem -= (*tmp).size ();
+				self.len -= 1;
+				Some (Node::free (tmp))
 			}
 			else {
 				None
@@ -432,7 +1103,38 @@ impl Index {
 	}
 }
 
-// TODO: impl Drop for Index
+impl Drop for Index {
+	fn drop (&mut self) {
+		let mut cur = self.head.0;
+		while !cur.is_null () {
+			unsafe {
+				let next=(*cur).nxt (0);
+				Node::free (cur);
+				cur = next;
+			}
+		}
+	}
+}
+
+struct Iter<'a> {
+	cur: *mut Node,
+	_ix: PhantomData<&'a Index>
+}
+
+impl<'a> Iterator for Iter<'a> {
+	type Item = &'a Arc<Record>;
+
+	fn next (&mut self)->Option<Self::Item> {
+		if self.cur.is_null () {
+			return None
+		}
+
+		let node=unsafe { &*self.cur };
+		self.cur = node.nxt (0);
+
+		Some (&node.elem)
+	}
+}
 
 const LIMS: [u32;MAXLVL+1] = {
 	let mut lims = [0;MAXLVL+1];
@@ -444,11 +1146,19 @@ const LIMS: [u32;MAXLVL+1] = {
 	lims
 };
 
-// Determine the maxlvl for an element being inserted
-fn get_maxlvl (rval:u32)->usize {
-	for (i,item) in LIMS.iter ().enumerate () {
+/* The highest level an element may get when the index has len of them: the bit length of len, floor(log2 len)+1,
+   so 0 for an empty index (the first element only gets level 0), 1 for len 1 and 3 for len 4. That keeps
+   the index balanced whatever its size. As it grows the new elements get higher, as it shrinks lower,
+   the tall ones there are staying until deleted. */
+fn top_lvl (len:u32)->usize {
+	(u32::BITS - len.leading_zeros ()) as usize
+}
+
+// Determine the maxlvl for an element being inserted, from rval in 0..=LIMS[top]
+fn get_maxlvl (rval:u32, top:usize)->usize {
+	for (i,item) in LIMS[..=top].iter ().enumerate () {
 		if rval <= *item {
-			return MAXLVL-i
+			return top-i
 		}
 	}
 	unreachable! ();
@@ -458,6 +1168,8 @@ fn get_maxlvl (rval:u32)->usize {
 #[cfg (test)]
 mod tests {
 	use super::*;
+	use std::collections::{BTreeMap,btree_map::Entry};
+	use quickcheck::{quickcheck,Arbitrary,Gen,QuickCheck,TestResult};
 
 	fn print_off (dt:&Dt, &off:&usize, r:&Record)->String {
 		let r=r as *const Record as *const u8;
@@ -471,9 +1183,8 @@ mod tests {
 					let r= *(r.add (off) as *const i32);
 					format! ("{}", r)
 				}
-				Dt::STR=> {
-					let r=str::from_utf8 (&slice::from_raw_parts (r.add (off),SLEN)).unwrap ();
-					format! ("{}", r)
+				Dt::Str=> {
+					String::from_utf8_lossy (slice::from_raw_parts (r.add (off),SLEN)).to_string ()
 				}
 			}
 		}
@@ -481,17 +1192,46 @@ mod tests {
 
 	#[test]
 	fn check_lims () {
-		assert_eq! (LIMS, [0,1,3,7,15,31,63,127,255,511,1023,2047,4095,8191,16383,32767,65535,131071,262143,524287,1048575,2097151,4194303,8388607,16777215]);
+		assert_eq! (LIMS[..25], [0,1,3,7,15,31,63,127,255,511,1023,2047,4095,8191,16383,32767,65535,131071,262143,524287,1048575,2097151,4194303,8388607,16777215]);
+		assert_eq! (LIMS[MAXLVL], u32::MAX);
 	}
 
 	#[test]
 	fn maxlvl () {
-		assert_eq! (24,get_maxlvl (0));
-		assert_eq! (23,get_maxlvl (1));
-		assert_eq! (22,get_maxlvl (2));
-		assert_eq! (22,get_maxlvl (3));
-		assert_eq! (21,get_maxlvl (4));
-		assert_eq! (0,get_maxlvl (16777210));
+		assert_eq! (24,get_maxlvl (0,24));
+		assert_eq! (23,get_maxlvl (1,24));
+		assert_eq! (22,get_maxlvl (2,24));
+		assert_eq! (22,get_maxlvl (3,24));
+		assert_eq! (21,get_maxlvl (4,24));
+		assert_eq! (0,get_maxlvl (16777210,24));
+		assert_eq! ((32,0), (get_maxlvl (0,MAXLVL), get_maxlvl (u32::MAX,MAXLVL)));
+		assert_eq! ((0,0,1), (get_maxlvl (0,0), get_maxlvl (1,1), get_maxlvl (0,1)));
+
+		assert_eq! ([0,1,2,3,4,1023,1024,u32::MAX].map (top_lvl), [0,1,2,2,3,10,11,32]);
+	}
+
+	// The levels follow the size: about log2 of it, however many there are and have been
+	#[test]
+	fn levels () {
+		let mut ix=Index::new (Column::Id, offset_of! (Record,id), Dt::U32, false);
+		let mut n=0;
+		for size in [10,1000,1<<17] {
+			while n<size {
+				ix.insert (Arc::new (Record { id: n, ..Default::default () }));
+				n += 1;
+			}
+			let top=top_lvl (size);
+			assert! (ix.l<=top+1 && ix.l+5>=top, "{size}: {}", ix.l);
+		}
+
+		// shrinking: the new ones keep low, the levels go as the tall ones do
+		for id in 16..n {
+			ix.delete (&Record { id, ..Default::default () }, false).unwrap ();
+		}
+		for id in 16..1000 {
+			ix.insert (Arc::new (Record { id, ..Default::default () }));
+		}
+		assert! (ix.l<=top_lvl (999)+1 && ix.len==1000, "{}", ix.l);
 	}
 
 	#[test]
@@ -505,11 +1245,21 @@ mod tests {
 	}
 
 	impl Index {
+		// The nodes in order, the head not included
+		fn iter_nodes (&self)->impl Iterator<Item=&Node> {
+			let mut cur=unsafe { (*self.head.0).nxt (0) };
+			std::iter::from_fn (move || {
+				let node=unsafe { cur.as_ref ()? };
+				cur = node.nxt (0);
+				Some (node)
+			})
+		}
+
 		#[allow (dead_code)]
 		fn print (&self) {
 			println! ("Index = {self}");
 			unsafe {
-				let a=(*self.head.0).next[0].0;
+				let a=(*self.head.0).nxt (0);
 				if a.is_null () {
 					println! ("null");
 				}
@@ -526,15 +1276,15 @@ mod tests {
 
 			let mut cur=self.head.0;
 			unsafe {
-				while !(*cur).next[0].0.is_null () {
+				while !(*cur).nxt (0).is_null () {
 					if cur!=self.head.0 {
 						write! (f, ",")?;
 					}
 
-					let el=print_off (&self.dt, &self.off, &(*(*cur).next[0].0).elem);
+					let el=print_off (&self.dt, &self.off, &(*(*cur).nxt (0)).elem);
 					write! (f,"{el}")?;
 
-					cur = (*cur).next[0].0;
+					cur = (*cur).nxt (0);
 				}
 			}
 
@@ -545,7 +1295,7 @@ mod tests {
 	#[test]
 	fn basic_index () {
 		// New
-		let mut sl=Index::new (offset_of! (Record,id), Dt::U32);
+		let mut sl=Index::new (Column::Id, offset_of! (Record,id), Dt::U32, true);
 		assert_eq! (format! ("{sl}"), "[]");
 		// sl.print ();
 
@@ -581,7 +1331,7 @@ mod tests {
 		// Lookup
 		let a=[3,4,20,23,35,35,40,50,80,90,130,150,170,454,642,46442];
 		for n in 0..a.len () {
-			let el=(*sl.lookup (n as u32+1).elem).id;
+			let el=sl.lookup (n as u32+1).elem.id;
 			assert_eq! (el, a[n]);
 		}
 
@@ -616,18 +1366,534 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn cursor () {
+		let req=|col,bw,cur,key| Request { bw, cur, key, ..Request::new (col, 10) };
+		let ids=|recs:&[Arc<Record>]| recs.iter ().map (|r| r.id).collect::<Vec<_>> ();
+
+		for col in [Column::Id, Column::Num] {
+			for bw in [false,true] {
+				let mut t=Table::new ();
+				for id in 0..100 {
+					t.add (Record::new (id*2, (id%7) as i32, "x")).unwrap ();
+				}
+
+				/* Paging through visits every row once, and back again the same pages */
+				let (_,mut page)=t.fetch_recs (Request { CS: 0, NS: 91, ..req (col,bw,Cursor::Pos,Record::default ()) });
+				let mut pages=vec![ids (&page)];
+				while pages.len ()<10 {
+					page = t.fetch_recs (req (col,bw,Cursor::After,*page[9])).1;
+					pages.push (ids (&page));
+				}
+				let mut all=pages.concat ();
+				all.sort ();
+				assert_eq! (all, (0..100).map (|id| id*2).collect::<Vec<_>> ());
+
+				for i in (0..9).rev () {
+					page = t.fetch_recs (req (col,bw,Cursor::Before,*page[0])).1;
+					assert_eq! (ids (&page), pages[i]);
+				}
+
+				/* A page stays put while rows come and go on both sides of it */
+				let first=t.fetch_recs (Request { CS: 37, NS: 91, ..req (col,bw,Cursor::Pos,Record::default ()) }).1[0].clone ();
+				for id in 0..50 {
+					t.add (Record::new (id*2+1, (id%5) as i32, "y")).unwrap ();
+				}
+				for id in (0..200).step_by (6).filter (|&id| id!=first.id) {
+					t.rm (id).unwrap ();
+				}
+
+				let (resp,recs)=t.fetch_recs (req (col,bw,Cursor::At,*first));
+				assert! (resp.ok && Arc::ptr_eq (&recs[0], &first));
+				assert_eq! (t.fetch_recs (req (col,bw,Cursor::After,*first)).1[0].id, recs[1].id);
+
+				// the row is gone: the page starts where it used to be
+				t.rm (first.id).unwrap ();
+				let (_,gone)=t.fetch_recs (req (col,bw,Cursor::At,*first));
+				assert_eq! (gone[0].id, recs[1].id);
+			}
+		}
+	}
+
+	#[test]
+	fn expr_index () {
+		let mut t=Table::new ();
+		for id in 0..300 {
+			t.add (Record::new (id, (id as i32*37)%101-50, ["ab","AB","Ac","b","aB"][id as usize % 5])).unwrap ();
+		}
+		assert! (t.create_index (Column::Num, Expr::Abs).is_ok ());
+		assert! (t.create_index (Column::Num, Expr::Abs).is_err ());
+		assert! (t.create_index (Column::Str, Expr::Abs).is_err ());
+		assert_eq! (t.create_index (Column::Id, Expr::Abs), Err (Error::Invalid));
+		assert! (t.create_index (Column::Num, Expr::Lower).is_err ());
+
+		// filled before, and kept up to date after it's created
+		for id in 300..400 {
+			t.add (Record::new (id, id as i32-350, "ac")).unwrap ();
+		}
+		t.create_index (Column::Str, Expr::Lower).unwrap ();
+		for id in (0..400).step_by (3) {
+			t.rm (id).unwrap ();
+		}
+
+		let req=Request { expr: Expr::Abs, ..Request::new (Column::Num, 1000) };
+		for (col,expr,f) in [(Column::Num, Expr::Abs, None), (Column::Str, Expr::Lower, None), (Column::Num, Expr::Abs, Some ("num < 0 or str = 'b'"))] {
+			let f=f.map (|f| Filter::parse (f).unwrap ());
+			let mut want:Vec<&Record> = t.iter ().filter (|r| f.as_ref ().is_none_or (|f| f.eval (r))).collect ();
+			want.sort_by_key (|r| if col==Column::Num {(r.num.unsigned_abs (), [0;SLEN], r.id)} else {(0, r.str.map (|c| c.to_ascii_lowercase ()), r.id)});
+
+			let (resp,recs)=t.fetch_filtered (Request { col, expr, ..req }, f.as_ref ());
+			assert! (resp.ok);
+			assert_eq! (recs.iter ().map (|r| r.id).collect::<Vec<_>> (), want.iter ().map (|r| r.id).collect::<Vec<_>> ());
+
+			// the key cursors go by the expression as well
+			let (_,page)=t.fetch_filtered (Request { col, expr, bw: true, cur: Cursor::Before, N: 5, key: *recs[100], ..req }, f.as_ref ());
+			assert_eq! (page.iter ().map (|r| r.id).collect::<Vec<_>> (), want[101..106].iter ().rev ().map (|r| r.id).collect::<Vec<_>> ());
+		}
+
+		// no such index
+		assert! (!t.fetch_recs (Request { col: Column::Id, expr: Expr::Abs, ..req }).0.ok);
+	}
+
+	#[test]
+	fn nulls () {
+		let mut t=Table::new ();
+		for id in 0..200 {
+			let mut r=Record::new (id, id as i32%13-6, ["ab","b","c"][id as usize % 3]);
+			if id%5==0 {
+				r = r.null (Column::Num);
+			}
+			if id%7==0 {
+				r = r.null (Column::Str);
+			}
+			t.add (r).unwrap ();
+		}
+		assert! (t.add (Record::new (500, 0, "").null (Column::Id)).is_err ());
+		let row1=|t:&Table| t.iter ().find (|r| r.id==1).copied ();
+		let (seq,was) = (t.seq (), row1 (&t));
+		assert_eq! (t.upd (Record::new (1, 0, "").null (Column::Id)), Err (Error::NullId));
+		assert_eq! ((t.tot (), t.seq (), row1 (&t)), (200, seq, was));  // the row is left as it was
+		assert_eq! (Record::new (1, 0, "x").null (Column::Num).to_string (), "id=1, num=NULL, str=x   ");
+
+		let req=Request::new (Column::Num, 1000);
+		for nulls in [Nulls::Last, Nulls::First] {
+			t.set_nulls (Column::Num, nulls).unwrap ();
+			t.set_nulls (Column::Str, nulls).unwrap ();
+
+			for col in [Column::Num, Column::Str] {
+				// the NULLs at the end (or at the start) in the id order, the values sorted in between
+				let recs=t.fetch_recs (Request { col, ..req }).1;
+				let mut want:Vec<&Record> = t.iter ().collect ();
+				want.sort_by_key (|r| (r.is_null (col)==(nulls==Nulls::Last), if col==Column::Num {(r.num,[0;SLEN])} else {(0,r.str)}, r.id));
+				assert_eq! (recs.iter ().map (|r| r.id).collect::<Vec<_>> (), want.iter ().map (|r| r.id).collect::<Vec<_>> (), "{col:?} {nulls:?}");
+
+				// a page that starts on a NULL stays there
+				let at=t.fetch_recs (Request { col, bw: true, cur: Cursor::At, N: 3, key: *want[20], ..req }).1;
+				assert_eq! (at.iter ().map (|r| r.id).collect::<Vec<_>> (), [want[20].id, want[19].id, want[18].id]);
+			}
+
+			// comparisons never pass a NULL, nor do their negations
+			for expr in ["num is null", "num is not null", "num < 0", "not num < 0", "num >= -2 and str is null",
+			             "str like 'a%' or num is null", "not (num = 1 or str = 'b')", "str is not null and num != 3"] {
+				let f=Filter::parse (expr).unwrap ();
+				assert_eq! (Filter::parse (&f.to_string ()), Ok (f.clone ()));
+				assert_eq! (Filter::from_bytes (&f.to_bytes ()), Some (f.clone ()));
+
+				let want:Vec<u32> = t.iter ().filter (|r| f.eval (r)).map (|r| r.id).collect ();
+				for col in [Column::Id, Column::Num, Column::Str] {
+					let (_,recs)=t.fetch_filtered (Request { col, ..req }, Some (&f));
+					let mut got:Vec<u32> = recs.iter ().map (|r| r.id).collect ();
+					got.sort ();
+					assert_eq! (got, want, "{expr} {col:?} {nulls:?}");
+				}
+			}
+			assert_eq! (t.iter ().filter (|r| Filter::parse ("num is null").unwrap ().eval (r)).count (), 40);
+			assert_eq! (t.iter ().filter (|r| Filter::parse ("not num < 0").unwrap ().eval (r)).count (), 160-t.iter ().filter (|r| !r.is_null (Column::Num) && r.num<0).count ());
+
+			// aggregates leave the NULLs out, the groups have them as one
+			let agg=t.aggregate (Column::Num, None).unwrap ();
+			let vals:Vec<i64> = t.iter ().filter (|r| !r.is_null (Column::Num)).map (|r| r.num as i64).collect ();
+			assert_eq! ((agg.count as usize, agg.sum), (vals.len (), Some (vals.iter ().sum ())));
+			assert_eq! ((agg.min.unwrap ().num, agg.max.unwrap ().num), (-6, 6));
+			assert_eq! (t.aggregate (Column::Num, Some (&Filter::parse ("num is null").unwrap ())).unwrap ().count, 0);
+			assert_eq! (t.aggregate (Column::Num, Some (&Filter::parse ("str is null").unwrap ())).unwrap ().count, 29-6);
+
+			let groups=t.group_by (Column::Num, 0);
+			let null=groups.iter ().find (|g| g.key.is_null (Column::Num)).unwrap ();
+			assert_eq! ((groups.len (), null.count), (14, 40));
+		}
+	}
+
+	#[test]
+	fn delete () {
+		let mut t=Table::new ();
+		for id in 0..1000 {
+			let r=Record::new (id, (id as i32*7919)%2003-1000, ["ab","abc","b","c"][id as usize % 4]);
+			t.add (if id%9==0 {r.null (Column::Num)} else {r}).unwrap ();
+		}
+		t.create_index (Column::Num, Expr::Abs).unwrap ();
+		let mut left:Vec<Record> = t.iter ().copied ().collect ();
+
+		let check=|t:&Table, left:&Vec<Record>| {
+			assert_eq! (t.iter ().copied ().collect::<Vec<_>> (), *left);
+			for (col,expr) in [(Column::Id,Expr::Field), (Column::Num,Expr::Field), (Column::Str,Expr::Field), (Column::Num,Expr::Abs)] {
+				let req=Request { expr, ..Request::new (col, 2000) };
+				let (resp,recs)=t.fetch_recs (req);
+				assert! (resp.ok && recs.len ()==left.len () && t.tot ()==left.len () as u32);
+			}
+			let sum=left.iter ().filter (|r| !r.is_null (Column::Num)).map (|r| r.num as i64).sum::<i64> ();
+			assert_eq! (t.aggregate (Column::Num, None).unwrap ().sum, Some (sum));
+		};
+
+		for expr in ["str like 'ab%' and num > 500", "id >= 900", "num is null and id < 300", "str = 'zz'"] {
+			let f=Filter::parse (expr).unwrap ();
+			let n=left.len ();
+			left.retain (|r| !f.eval (r));
+			assert_eq! (t.delete_where (&f) as usize, n-left.len (), "{expr}");
+			check (&t, &left);
+		}
+
+		let num=|n| Record::new (0, n, "");
+		for (col,range) in [(Column::Num, (Bound::Included (num (-100)), Bound::Excluded (num (100)))),
+		                    (Column::Id , (Bound::Excluded (Record::new (10, 0, "")), Bound::Included (Record::new (20, 0, "")))),
+		                    (Column::Str, (Bound::Included (Record::new (0, 0, "b")), Bound::Unbounded)),
+		                    (Column::Num, (Bound::Unbounded, Bound::Unbounded))] {
+			let within=|r:&Record| {
+				let (lo,hi) = (&range.0, &range.1);
+				let v=|x:&Record| match col {
+					Column::Id  => (x.id as i64, [0;SLEN]),
+					Column::Num => (x.num as i64, [0;SLEN]),
+					_           => (0, x.str)
+				};
+				!r.is_null (col) &&
+				match lo {Bound::Included (b) => v (r)>=v (b), Bound::Excluded (b) => v (r)>v (b), _ => true} &&
+				match hi {Bound::Included (b) => v (r)<=v (b), Bound::Excluded (b) => v (r)<v (b), _ => true}
+			};
+			let n=left.len ();
+			left.retain (|r| !within (r));
+			assert_eq! (t.delete_range (col, range) as usize, n-left.len (), "{col:?}");
+			check (&t, &left);
+		}
+		assert! (left.iter ().all (|r| r.is_null (Column::Num)) && !left.is_empty ());
+	}
+
+	#[test]
+	fn exact_pos () {
+		// f32 lands off by a few rows here
+		let (tot,N) = (10_000_000, 10);
+		let NS=tot-N+1;
+		assert_eq! (1 + scale (NS-1, tot-N, NS-1), tot-N+1);
+		assert_eq! (1 + scale (NS-2, tot-N, NS-1), tot-N);
+	}
+
+	quickcheck! {
+		/* Whichever screens the client has, they all land on their own position, and the ends on the ends.
+		   With as many screens as there are positions, every position is reachable. */
+		fn screens (tot:u32, N:u16, NS:u32, CS:u32)->TestResult {
+			let N=N as u32 + 1;
+			if tot<=N {
+				return TestResult::discard ()
+			}
+			let last=tot-N+1;                  // positions (1-based) a full page can start at
+			let NS=NS%last + 1;
+			let pos=|CS| 1 + scale (CS, tot-N, cmp::max (1, NS-1));
+
+			if NS==1 {
+				return TestResult::from_bool (pos (0)==1)
+			}
+			let CS=CS%(NS-1);
+			TestResult::from_bool (pos (0)==1 && pos (NS-1)==last && pos (CS)<pos (CS+1) &&
+			                       (NS<last || pos (CS)==CS+1))
+		}
+
+		// The knob positions of the client: from the first screen to the last one, none twice
+		fn knob (NS:u32, npos:u8, i:u8)->TestResult {
+			let npos=cmp::min (npos as u32 % 16 + 2, NS);
+			if npos<2 {
+				return TestResult::discard ()
+			}
+			let i=i as u32 % (npos-1);
+			let kl=|i| scale (i, NS-1, npos-1);
+			TestResult::from_bool (kl (0)==0 && kl (npos-1)==NS-1 && kl (i)<kl (i+1))
+		}
+
+		// Every row of a table can be the top one, in either direction
+		fn reachable (nums:Vec<i8>, N:u8, bw:bool)->bool {
+			let N=N as u32 % 8 + 1;
+			let mut t=Table::new ();
+			let mut rows:Vec<(i32,u32)> = vec![];
+			for (id,&num) in nums.iter ().enumerate () {
+				t.add (Record::new (id as u32, num as i32, "x")).unwrap ();
+				rows.push ((num as i32, id as u32));
+			}
+			rows.sort ();
+			if bw {
+				rows.reverse ();
+			}
+
+			let req=Request { bw, ..Request::new (Column::Num, N) };
+			let NS=t.fetch_recs (req).0.NS;
+			(0..NS).all (|CS| {
+				let (resp,recs)=t.fetch_recs (Request { CS, NS, ..req });
+				let top=CS as usize;
+				resp.CS==CS && recs.iter ().map (|r| (r.num,r.id)).eq (rows[top..rows.len ().min (top+N as usize)].iter ().copied ())
+			})
+		}
+	}
+
 	#[test]
 	fn basic_table () {
 		let mut t=Table::new ();
+		let rng=&mut thread_rng ();
 
 		let id=0;
-		assert! (t.add (Record::gen (id)).is_ok ());
-		assert! (t.upd (Record::gen (id)).is_ok ());
+		assert! (t.add (Record::gen (id, rng)).is_ok ());
+		assert! (t.upd (Record::gen (id, rng)).is_ok ());
 
-		assert! (t.add (Record::gen (id)).is_err ());
+		assert! (t.add (Record::gen (id, rng)).is_err ());
 		assert! (t.rm (id).is_ok ());
 
 		assert! (t.rm (id).is_err ());
-		assert! (t.upd (Record::gen (id)).is_err ());
+		assert! (t.upd (Record::gen (id, rng)).is_err ());
+	}
+
+	// The same seed and the same changes make the same records and the same skip-lists
+	#[test]
+	fn seeded () {
+		let run=|seed:u64| {
+			let (mut t, mut rng) = (Table::with_seed (seed), StdRng::seed_from_u64 (seed));
+			t.create_index (Column::Str, Expr::Lower).unwrap ();
+			for id in 0..1000 {
+				t.add (Record::gen (id, &mut rng)).unwrap ();
+				if id%3==0 {
+					t.rm (rng.gen_range (0..=id)).unwrap_or_default ();
+				}
+			}
+			t.set_nulls (Column::Num, Nulls::First).unwrap ();
+			t.upd (Record::gen (999, &mut rng)).unwrap ();
+
+			let lvls=|ix:&Index| ix.iter_nodes ().map (|n| n.lvls ()).collect::<Vec<_>> ();
+			let recs:Vec<Record> = t.iter ().copied ().collect ();
+			(recs, t.fields.iter ().map (lvls).collect::<Vec<_>> (), lvls (&t.exprs[0].1))
+		};
+		assert! (run (1)==run (1));
+		assert! (run (1)!=run (2));
+
+		let mut t=Table::new ();
+		t.reseed (7);
+		for id in 0..100 {
+			t.add (Record::new (id, 0, "")).unwrap ();
+		}
+		let mut u=Table::with_seed (7);
+		for id in 0..100 {
+			u.add (Record::new (id, 0, "")).unwrap ();
+		}
+		assert_eq! (t.memory_usage (), u.memory_usage ());
+	}
+
+	/* Model-based tests: long random runs of changes and fetches, against a BTreeMap of the rows,
+	   every result compared with the model's and the table verified after each step.
+	   quickcheck shrinks a failing run down to the few steps that still fail. */
+	#[derive (Clone, Debug)]
+	enum Op {
+		Add (Record),
+		Rm (u32),
+		Upd (Record),
+		Fetch (Request)
+	}
+
+	// Few ids, nums and strs, so that the changes hit the rows there are and the indexes have runs of equal values
+	fn small_rec (g:&mut Gen)->Record {
+		let r=Record::new (u8::arbitrary (g) as u32 % 128, i8::arbitrary (g) as i32 % 8, g.choose (&["", "a", "A", "b", "B", "ab", "aB", "Ba"]).unwrap ());
+		match u8::arbitrary (g) % 8 {
+			0 => r.null (Column::Num),
+			1 => r.null (Column::Str),
+			_ => r
+		}
+	}
+
+	impl Arbitrary for Op {
+		fn arbitrary (g:&mut Gen)->Self {
+			match u8::arbitrary (g) % 8 {
+				0..=2 => Op::Add (small_rec (g)),
+				3     => Op::Rm (u8::arbitrary (g) as u32 % 128),
+				4     => Op::Upd (small_rec (g)),
+				_     => {
+					let (col,expr) = *g.choose (&[(Column::Id,Expr::Field), (Column::Num,Expr::Field), (Column::Num,Expr::Abs),
+					                              (Column::Str,Expr::Field), (Column::Str,Expr::Lower)]).unwrap ();
+					Op::Fetch (Request {
+						expr,
+						bw : bool::arbitrary (g),
+						cur: *g.choose (&[Cursor::Pos, Cursor::At, Cursor::After, Cursor::Before]).unwrap (),
+						CS : u8::arbitrary (g) as u32 % 13,
+						NS : u8::arbitrary (g) as u32 % 12,
+						key: small_rec (g),
+						..Request::new (col, u8::arbitrary (g) as u32 % 10)  // 0 and CS>=NS are refused
+					})
+				}
+			}
+		}
+
+		fn shrink (&self)->Box<dyn Iterator<Item=Self>> {
+			match *self {
+				Op::Fetch (req) if req.N>1 => Box::new ([Op::Fetch (Request { N: req.N/2, ..req })].into_iter ()),
+				_                           => Box::new (std::iter::empty ())
+			}
+		}
+	}
+
+	// The order of an index, as a key to sort by: NULLs last, equal values by the id
+	fn model_key (col:Column, expr:Expr, r:&Record)->(bool,i64,Vec<u8>,u32) {
+		let null=r.is_null (col);
+		match (col,expr) {
+			_ if null                      => (true, 0, vec![], r.id),
+			(Column::Id , _)               => (false, r.id as i64, vec![], r.id),
+			(Column::Num, Expr::Abs)       => (false, (r.num as i64).abs (), vec![], r.id),
+			(Column::Num, _)               => (false, r.num as i64, vec![], r.id),
+			(_          , Expr::Lower)     => (false, 0, r.str.to_ascii_lowercase (), r.id),
+			_                              => (false, 0, r.str.to_vec (), r.id)
+		}
+	}
+
+	// What a fetch should give: ok, CS, NS and the rows
+	fn model_fetch (m:&BTreeMap<u32,Record>, req:Request)->(bool,u32,u32,Vec<Record>) {
+		if req.N==0 || (req.cur==Cursor::Pos && (req.NS==0 || req.CS>=req.NS)) {
+			return (false, 0, 1, vec![])
+		}
+		let key=|r:&Record| model_key (req.col, req.expr, r);
+		let mut rows:Vec<Record> = m.values ().copied ().collect ();
+		rows.sort_by_key (key);
+		let (tot,N) = (rows.len () as i64, req.N as i64);
+		if tot==0 {
+			return (true, 0, 1, vec![])
+		}
+
+		// the first row of the page, in the direction of the request
+		let k=key (&{req.key});
+		let lt=rows.iter ().filter (|r| key (r)<k).count () as i64;
+		let le=rows.iter ().filter (|r| key (r)<=k).count () as i64;
+		let start=match (req.cur, req.bw) {
+			(Cursor::Pos   , _    ) => if req.NS>1 && tot>N {scale (req.CS, (tot-N) as u32, req.NS-1) as i64} else {0},
+			(Cursor::At    , false) => lt,
+			(Cursor::After , false) => le,
+			(Cursor::Before, false) => lt-N,
+			(Cursor::At    , true ) => tot-le,
+			(Cursor::After , true ) => tot-lt,
+			(Cursor::Before, true ) => tot-le-N,
+			_                       => unreachable! ()
+		}.clamp (0, cmp::max (0, tot-N)) as usize;
+
+		if req.bw {
+			rows.reverse ();
+		}
+		let page=rows[start..cmp::min (start+N as usize, rows.len ())].to_vec ();
+		(true, start as u32, if tot>N {(tot-N+1) as u32} else {1}, page)
+	}
+
+	fn model_run (seed:u64, ops:Vec<Op>)->TestResult {
+		let mut t=Table::with_seed (seed);
+		t.create_index (Column::Num, Expr::Abs).unwrap ();
+		t.create_index (Column::Str, Expr::Lower).unwrap ();
+		let mut m=BTreeMap::new ();
+
+		for (i,op) in ops.iter ().enumerate () {
+			let fail=|what:String| TestResult::error (format! ("step {i}, {op:?}: {what}"));
+			match *op {
+				Op::Add (r) => {
+					let want=match m.entry (r.id) {
+						Entry::Vacant (e)   => { e.insert (r); Ok (()) }
+						Entry::Occupied (_) => Err (Error::Exists)
+					};
+					let got=t.add (r);
+					if got!=want {
+						return fail (format! ("{got:?} rather than {want:?}"))
+					}
+				}
+				Op::Rm (id) => {
+					let want=m.remove (&id).map (|_| ()).ok_or (Error::NotFound);
+					let got=t.rm (id);
+					if got!=want {
+						return fail (format! ("{got:?} rather than {want:?}"))
+					}
+				}
+				Op::Upd (r) => {
+					let want=m.get_mut (&r.id).map (|v| *v = r).ok_or (Error::NotFound);
+					let got=t.upd (r);
+					if got!=want {
+						return fail (format! ("{got:?} rather than {want:?}"))
+					}
+				}
+				Op::Fetch (req) => {
+					let (resp,recs) = t.fetch_recs (req);
+					let got=(resp.ok, resp.CS, resp.NS, recs.iter ().map (|r| **r).collect::<Vec<_>> ());
+					let want=model_fetch (&m, req);
+					if got!=want {
+						return fail (format! ("{got:?} rather than {want:?}"))
+					}
+				}
+			}
+			if t.tot () as usize!=m.len () {
+				return fail (format! ("tot {} rather than {}", t.tot (), m.len ()))
+			}
+			if let Err (e)=t.verify () {
+				return fail (e)
+			}
+		}
+		TestResult::passed ()
+	}
+
+	#[test]
+	fn model () {
+		QuickCheck::new ().rng (Gen::new (400)).tests (100).quickcheck (model_run as fn (u64,Vec<Op>)->TestResult);
+	}
+
+	/* A bare index may have equal keys (a table's never do): a row is deleted by its Arc from among its equals.
+	   The model is the rows in order, each new one going before its equals, as the index puts it. */
+	fn dups_run (seed:u64, ops:Vec<(bool,u8)>)->TestResult {
+		let mut ix=Index::new (Column::Num, offset_of! (Record,num), Dt::I32, true);
+		ix.rng = StdRng::seed_from_u64 (seed);
+		let mut m:Vec<Arc<Record>> = vec![];
+
+		for (i,&(ins,k)) in ops.iter ().enumerate () {
+			let fail=|what:String| TestResult::error (format! ("step {i}, {:?}: {what}", (ins,k)));
+			if ins || m.is_empty () {
+				let r=Arc::new (Record { id: 1, num: k as i32 % 4, ..Default::default () });
+				let at=m.partition_point (|x| x.num<r.num);
+				m.insert (at, r.clone ());
+				ix.insert (r);
+			}
+			else {
+				let r=m.remove (k as usize % m.len ());
+				if !ix.delete (&r, true).is_some_and (|got| Arc::ptr_eq (&got,&r)) {
+					return fail ("not the row deleted".to_string ())
+				}
+			}
+
+			if !ix.iter ().map (Arc::as_ptr).eq (m.iter ().map (Arc::as_ptr)) {
+				return fail ("not the rows in order".to_string ())
+			}
+			if let Err (e)=ix.verify () {
+				return fail (e)
+			}
+			for n in 1..=m.len () {
+				let sum:i64=m[..n].iter ().map (|r| r.num as i64).sum ();
+				if !Arc::ptr_eq (&ix.lookup (n as u32).elem, &m[n-1]) || ix.sum (n as u32)!=sum {
+					return fail (format! ("lookup or sum of {n}"))
+				}
+			}
+			for num in 0..4 {
+				let key=Record { id: 1, num, ..Default::default () };
+				let (lt,le) = (m.partition_point (|x| x.num<num) as u32, m.partition_point (|x| x.num<=num) as u32);
+				if (ix.count (&key,false), ix.count (&key,true))!=(lt,le) {
+					return fail (format! ("count of {num}"))
+				}
+			}
+		}
+		TestResult::passed ()
+	}
+
+	#[test]
+	fn dups () {
+		QuickCheck::new ().rng (Gen::new (300)).tests (200).quickcheck (dups_run as fn (u64,Vec<(bool,u8)>)->TestResult);
 	}
 }
@@ -0,0 +1,120 @@
+/* Memory accounting: the bytes a table takes, those of the records (each in an Arc of its own, which the indexes
+	share) and those of the nodes of each index, kept up to date as the nodes come and go. Only the table's own
+	allocations are counted: not the allocator's overhead, nor the events kept for the change stream.
+	With a cap, an add is refused (Error::Full) once the table has got to it, rather than the process
+	running out of memory; the rest of the changes don't grow the table, so they still go.
+ */
+
+use crate::*;
+
+const ARC_SZ:usize = 2*mem::size_of::<usize> () + REC_SZ;  // the allocation of an Arc<Record>: the counts and the record
+
+#[derive (Debug, Clone, PartialEq)]
+pub struct Memory {
+	pub records: usize,
+	pub indexes: Vec<(Column,Expr,usize)>,  // the columns' indexes first, then those on the expressions
+	pub cap    : Option<usize>
+}
+
+impl Memory {
+	pub fn total (&self)->usize {
+		self.records + self.indexes.iter ().map (|&(_,_,n)| n).sum::<usize> ()
+	}
+
+	// The bytes of each part and the total, as a table of text
+	pub fn text (&self)->String {
+		let mut rows=vec![vec!["part".to_string (), "bytes".to_string ()], vec!["records".to_string (), self.records.to_string ()]];
+		for &(col,expr,n) in &self.indexes {
+			rows.push (vec![format! ("index {}", index_name (col,expr)), n.to_string ()]);
+		}
+		rows.push (vec!["total".to_string (), self.total ().to_string ()]);
+		if let Some (cap)=self.cap {
+			rows.push (vec!["cap".to_string (), cap.to_string ()]);
+		}
+		query::columns (&rows)
+	}
+}
+
+impl Table {
+	pub fn memory_usage (&self)->Memory {
+		Memory {
+			records: self.records_mem (),
+			indexes: self.indexes ().map (|ix| (ix.col, ix.expr, ix.mem)).collect (),
+			cap    : self.cap
+		}
+	}
+
+	// What Memory::total is, without the list of the indexes: for the cap, checked on every add
+	fn used (&self)->usize {
+		self.records_mem () + self.indexes ().map (|ix| ix.mem).sum::<usize> ()
+	}
+
+	fn records_mem (&self)->usize {
+		self.tot as usize * ARC_SZ
+	}
+
+	// None for no cap
+	pub fn set_memory_cap (&mut self, cap:Option<usize>) {
+		self.cap = cap;
+	}
+
+	// Whether the table may grow by another row
+	pub(crate) fn room (&self)->Result<(),Error> {
+		match self.cap {
+			Some (cap) => {
+				let used=self.used ();
+				if used>=cap {Err (Error::Full {used, cap})} else {Ok (())}
+			}
+			None => Ok (())
+		}
+	}
+}
+
+#[cfg (test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn memory () {
+		let mut t=Table::new ();
+		t.create_index (Column::Num, Expr::Abs).unwrap ();
+		let empty=t.memory_usage ();
+		assert_eq! ((empty.records, empty.indexes.len ()), (0, 4));
+		assert! (empty.indexes.iter ().all (|&(_,_,n)| n>0));  // the heads
+
+		for id in 0..100 {
+			t.add (Record::new (id, id as i32, "")).unwrap ();
+		}
+		let m=t.memory_usage ();
+		assert_eq! (m.records, 100*ARC_SZ);
+		for (a,b) in m.indexes.iter ().zip (&empty.indexes) {
+			assert! (a.2>b.2 && (a.0,a.1)==(b.0,b.1));
+		}
+		assert! (m.indexes[1].2>m.indexes[2].2);  // num keeps the sums, str doesn't
+		assert_eq! ((m.total (), t.used ()), (m.records + m.indexes.iter ().map (|ix| ix.2).sum::<usize> (), m.total ()));
+		let text=m.text ();
+		assert! (text.contains ("index abs(num)") && text.lines ().last ().is_some_and (|l| l.starts_with ("total") && l.ends_with (&m.total ().to_string ())));
+
+		assert_eq! (t.delete_range (Column::Id, ..), 100);
+		assert_eq! (t.memory_usage (), empty);
+
+		// up to the cap and no further, until some room is made
+		t.set_memory_cap (Some (empty.total () + 10*m.total ()/100));
+		let mut id=0;
+		let err=loop {
+			match t.add (Record::new (id, 0, "")) {
+				Ok (())  => id += 1,
+				Err (e) => break e
+			}
+		};
+		assert! (matches! (err, Error::Full {used,cap} if used>=cap && cap==t.memory_usage ().cap.unwrap ()), "{err}");
+		assert! (id>1 && t.tot ()==id, "{id}");
+		assert_eq! ((t.rm (id-1), t.rm (id-1)), (Ok (()), Err (Error::NotFound)));
+		assert! (t.memory_usage ().total ()<t.memory_usage ().cap.unwrap ());  // as it was before the last add
+		assert_eq! (t.add (Record::new (id-1, 0, "")), Ok (()));
+		t.upd (Record::new (0, 1, "")).unwrap ();
+		t.set_memory_cap (None);
+		assert_eq! (t.add (Record::new (0, 0, "")), Err (Error::Exists));
+		assert_eq! (t.add (Record::new (id, 0, "").null (Column::Id)), Err (Error::NullId));
+	}
+}
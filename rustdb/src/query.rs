@@ -0,0 +1,374 @@
+/* Queries in a small SQL-like language:
+	SELECT * FROM t [WHERE <filter>] [ORDER BY id|num|str [ASC|DESC]] [LIMIT n] [OFFSET m]
+	SELECT count(*), sum(num), min(str), ... FROM t [WHERE <filter>]
+	CREATE TABLE t [COLLATE binary|nocase|natural] [NULLS FIRST|LAST] [INDEX abs(num)] [INDEX lower(str)]
+	DROP TABLE t
+	SHOW TABLES
+	SHOW MEMORY t
+	SHOW REPLICATION
+	VERIFY TABLE t
+	The rows come from the index of the ORDER BY column, narrowed down by the WHERE conditions on it,
+	and the OFFSET is a lookup in it rather than a walk.
+	On the wire a query goes instead of a Request: the QUERY byte, the tag, the text.
+ */
+
+use std::{
+	fmt::Write as _,
+	sync::Arc
+};
+
+use crate::*;
+use filter::{Parser,Tok,lex};
+
+pub const QUERY    :u8  = 0xFF;   // the first byte of a query message, never that of a Request
+pub const QUERY_MAX:u32 = 1000;   // rows a query returns at most, whatever the LIMIT
+
+#[derive (Debug, Clone, PartialEq)]
+pub enum Statement {
+	Select (Query),
+	Create (String,Schema),
+	Drop   (String),
+	Show,
+	Memory (String),
+	Replication,
+	Verify (String)
+}
+
+// The header fields (CS, NS), the records and the text of an answer
+type Answer = (u32,u32,Vec<Arc<Record>>,String);
+
+#[derive (Debug, Clone, PartialEq)]
+pub struct Query {
+	pub aggs  : Vec<(Agg,Column)>,  // none for SELECT *
+	pub table : String,
+	pub filter: Option<Filter>,
+	pub col   : Column,
+	pub bw    : bool,
+	pub limit : u32,
+	pub offset: u32
+}
+
+impl Query {
+	pub fn parse (s:&str)->Result<Self,String> {
+		let mut p=Parser { toks: lex (s)?, i: 0 };
+		let word=|p:&mut Parser| match p.next ()? {
+			Tok::Word (w) => Ok (w),
+			t             => Err (format! ("Expected a name instead of {t}"))
+		};
+		let num=|p:&mut Parser, what:&str| word (p)?.parse::<u32> ().map_err (|_| format! ("Bad {what}"));
+		let expect=|p:&mut Parser, kw:&str| if p.kw (kw) {Ok (())} else {Err (format! ("Expected {}", kw.to_uppercase ()))};
+
+		let sym=|p:&mut Parser, sym:&'static str| if p.peek ()==Some (&Tok::Sym (sym)) {p.i += 1; true} else {false};
+		let star=|p:&mut Parser| matches! (p.peek (), Some (Tok::Word (w)) if w=="*") && {p.i += 1; true};
+
+		expect (&mut p, "select")?;
+		let mut aggs=vec![];
+		if !star (&mut p) {
+			loop {
+				let agg=match word (&mut p)?.to_ascii_lowercase ().as_str () {
+					"count" => Agg::Count,
+					"sum"   => Agg::Sum,
+					"min"   => Agg::Min,
+					"max"   => Agg::Max,
+					"avg"   => Agg::Avg,
+					w       => return Err (format! ("Expected * or an aggregate instead of '{w}'"))
+				};
+				if !sym (&mut p, "(") {
+					return Err ("Expected '('".to_string ())
+				}
+				let col=if agg==Agg::Count && star (&mut p) {Column::Id} else {p.column ()?};
+				if !sym (&mut p, ")") {
+					return Err ("Expected ')'".to_string ())
+				}
+				aggs.push ((agg,col));
+
+				if !sym (&mut p, ",") {
+					break
+				}
+			}
+		}
+		expect (&mut p, "from")?;
+
+		let mut q=Query {
+			aggs,
+			table : word (&mut p)?,
+			filter: None,
+			col   : Column::Id,
+			bw    : false,
+			limit : QUERY_MAX,
+			offset: 0
+		};
+
+		if p.kw ("where") {
+			q.filter = Some (p.expr ()?);
+		}
+		if p.kw ("order") {
+			expect (&mut p, "by")?;
+			q.col = p.column ()?;
+			q.bw  = p.kw ("desc");
+			if !q.bw {
+				p.kw ("asc");
+			}
+		}
+		if p.kw ("limit") {
+			q.limit = num (&mut p, "LIMIT")?;
+		}
+		if p.kw ("offset") {
+			q.offset = num (&mut p, "OFFSET")?;
+		}
+		sym (&mut p, ";");
+
+		match p.peek () {
+			None    => Ok (q),
+			Some (t) => Err (format! ("Unexpected {t}"))
+		}
+	}
+
+	// The message to send
+	pub fn msg (text:&str, tag:u32)->Vec<u8> {
+		let mut buf=vec![QUERY];
+		buf.extend_from_slice (&tag.to_ne_bytes ());
+		buf.extend_from_slice (text.as_bytes ());
+		buf
+	}
+
+	// The tag and the text of a message, if it's a query
+	pub fn from_msg (buf:&[u8])->Option<(u32,&str)> {
+		if buf.first ()!=Some (&QUERY) || buf.len ()<5 {
+			return None
+		}
+		let tag=u32::from_ne_bytes (buf[1..5].try_into ().unwrap ());
+
+		Some ((tag, str::from_utf8 (&buf[5..]).ok ()?))
+	}
+}
+
+impl Statement {
+	pub fn parse (s:&str)->Result<Self,String> {
+		let mut p=Parser { toks: lex (s)?, i: 0 };
+		if matches! (p.peek (), Some (Tok::Word (w)) if w.eq_ignore_ascii_case ("select")) {
+			return Query::parse (s).map (Statement::Select)
+		}
+		let word=|p:&mut Parser| match p.next ()? {
+			Tok::Word (w) => Ok (w),
+			t             => Err (format! ("Expected a name instead of {t}"))
+		};
+		let table=|p:&mut Parser| if p.kw ("table") {word (p)} else {Err ("Expected TABLE".to_string ())};
+
+		let st=if p.kw ("create") {
+			let name=table (&mut p)?;
+			let mut schema=Schema::default ();
+			loop {
+				if p.kw ("collate") {
+					schema.collation = match word (&mut p)?.to_ascii_lowercase ().as_str () {
+						"binary"  => Collation::Binary,
+						"nocase"  => Collation::NoCase,
+						"natural" => Collation::Natural,
+						c         => return Err (format! ("Unknown collation '{c}'"))
+					};
+				}
+				else if p.kw ("nulls") {
+					schema.nulls = if p.kw ("first") {Nulls::First} else if p.kw ("last") {Nulls::Last} else {
+						return Err ("Expected FIRST or LAST".to_string ())
+					};
+				}
+				else if p.kw ("index") {
+					let f=word (&mut p)?.to_ascii_lowercase ();
+					if p.next ()?!=Tok::Sym ("(") {
+						return Err ("Expected '('".to_string ())
+					}
+					let ix=match (f.as_str (), p.column ()?) {
+						("abs"  , Column::Num) => (Column::Num, Expr::Abs),
+						("lower", Column::Str) => (Column::Str, Expr::Lower),
+						(f, col)              => return Err (format! ("No index on {f}({})", ["id","num","str"][col as usize]))
+					};
+					if p.next ()?!=Tok::Sym (")") {
+						return Err ("Expected ')'".to_string ())
+					}
+					if !schema.exprs.contains (&ix) {
+						schema.exprs.push (ix);
+					}
+				}
+				else {
+					break
+				}
+			}
+			Statement::Create (name, schema)
+		}
+		else if p.kw ("drop") {
+			Statement::Drop (table (&mut p)?)
+		}
+		else if p.kw ("verify") {
+			Statement::Verify (table (&mut p)?)
+		}
+		else if p.kw ("show") {
+			if p.kw ("tables") {Statement::Show} else if p.kw ("memory") {Statement::Memory (word (&mut p)?)}
+			else if p.kw ("replication") {Statement::Replication} else {
+				return Err ("Expected TABLES, MEMORY or REPLICATION".to_string ())
+			}
+		}
+		else {
+			return Err ("Expected SELECT, CREATE TABLE, DROP TABLE, SHOW or VERIFY TABLE".to_string ())
+		};
+
+		if p.peek ()==Some (&Tok::Sym (";")) {
+			p.i += 1;
+		}
+		match p.peek () {
+			None    => Ok (st),
+			Some (t) => Err (format! ("Unexpected {t}"))
+		}
+	}
+}
+
+/* The answer to a query message: the response header with the offset in CS and the total in NS,
+   followed by the records, or by the text (of the aggregates, say), or if it isn't ok, by the error message */
+pub(crate) fn answer (tag:u32, res:Result<Answer,String>)->Vec<u8> {
+	let mut resp=Response { ok: false, CS: 0, NS: 0, tag };
+
+	let (recs,text) = match res {
+		Ok ((cs,ns,recs,text)) => {
+			(resp.ok, resp.CS, resp.NS) = (true, cs, ns);
+			(recs, text)
+		}
+		Err (e) => (vec![], e)
+	};
+	let mut data=resp.encode (&recs);
+	data.extend_from_slice (text.as_bytes ());
+	data
+}
+
+// The cells lined up under each other, the first row being the names
+pub(crate) fn columns (rows:&[Vec<String>])->String {
+	let mut out=String::new ();
+	for row in rows {
+		for (i,v) in row.iter ().enumerate () {
+			let _=write! (out, "{}{v:<12}", if i>0 {"  "} else {""});
+		}
+		out = out.trim_end ().to_string () + "\n";
+	}
+	out
+}
+
+impl Table {
+	// The total of the matching rows, and those of them from the offset on
+	pub fn query (&self, q:&Query)->Result<(u32,Vec<Arc<Record>>),String> {
+		let rows=self.rows (&self.fields[q.col as usize], q.col, q.filter.as_ref (), &self.expired ()).ok_or ("The filter needs too long a scan, ORDER BY the filtered column")?;
+		let recs=rows.take (&self.fields[q.col as usize], q.bw, q.offset.saturating_add (1), cmp::min (q.limit, QUERY_MAX));
+
+		Ok ((rows.len (), recs))
+	}
+
+	// The aggregates of a query as a table: the names over the values
+	pub fn summary (&self, q:&Query)->Result<String,String> {
+		let (mut names, mut vals) = (vec![], vec![]);
+
+		for &(agg,col) in &q.aggs {
+			let a=self.aggregate (col, q.filter.as_ref ()).ok_or ("The filter needs too long a scan")?;
+			let field=|r:&Option<Arc<Record>>| r.as_ref ().map_or ("-".to_string (), |r| r.text (col).trim_end ().to_string ());
+
+			let name=["id","num","str"][col as usize];
+			let (name,val) = match agg {
+				Agg::Count if col==Column::Id => ("count(*)".to_string (), a.count.to_string ()),
+				Agg::Count => (format! ("count({name})"), a.count.to_string ()),
+				Agg::Sum   => (format! ("sum({name})"), a.sum.map_or ("-".to_string (), |s| s.to_string ())),
+				Agg::Min   => (format! ("min({name})"), field (&a.min)),
+				Agg::Max   => (format! ("max({name})"), field (&a.max)),
+				Agg::Avg   => (format! ("avg({name})"), a.avg ().map_or ("-".to_string (), |v| format! ("{v:.2}")))
+			};
+			names.push (name);
+			vals .push (val);
+		}
+
+		Ok (columns (&[names,vals]))
+	}
+
+	// The answer to a SELECT: the rows, or the text of the aggregates
+	pub fn select (&self, q:&Query)->Result<Answer,String> {
+		if q.aggs.is_empty () {
+			self.query (q).map (|(tot,recs)| (q.offset, tot, recs, String::new ()))
+		}
+		else {
+			self.summary (q).map (|s| (0, 0, vec![], s))
+		}
+	}
+
+	// The answer to a query message, whatever table it names
+	pub fn answer (&self, tag:u32, text:&str)->Vec<u8> {
+		answer (tag, Query::parse (text).and_then (|q| self.select (&q)))
+	}
+}
+
+#[cfg (test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse () {
+		let q=Query::parse ("select * from t where num > 10 and str like 'ab%' order by num desc limit 20 offset 100;").unwrap ();
+		assert_eq! (q, Query {
+			aggs  : vec![],
+			table : "t".to_string (),
+			filter: Some (Filter::parse ("num > 10 and str like 'ab%'").unwrap ()),
+			col   : Column::Num,
+			bw    : true,
+			limit : 20,
+			offset: 100
+		});
+		assert_eq! (Query::parse ("SELECT * FROM t ORDER BY str ASC").map (|q| (q.col,q.bw,q.limit)), Ok ((Column::Str,false,QUERY_MAX)));
+
+		assert_eq! (Query::parse ("select count(*), avg(num), max (str) from t").map (|q| q.aggs),
+		            Ok (vec![(Agg::Count,Column::Id), (Agg::Avg,Column::Num), (Agg::Max,Column::Str)]));
+
+		for bad in ["", "select id from t", "select count from t", "select sum(*) from t", "select min(id), from t",
+		            "select * t", "select * from t where", "select * from t order num", "select * from t limit x", "select * from t offset 1 limit 2", "select * from t;;"] {
+			assert! (Query::parse (bad).is_err (), "{bad}");
+		}
+	}
+
+	#[test]
+	fn query () {
+		let mut t=Table::new ();
+		for id in 0..300 {
+			t.add (Record::new (id, (id as i32*37)%101-50, ["ab","abc","b"][id as usize % 3])).unwrap ();
+		}
+
+		let ids=|sql:&str| {
+			let (tot,recs)=t.query (&Query::parse (sql).unwrap ()).unwrap ();
+			(tot, recs.iter ().map (|r| r.id).collect::<Vec<_>> ())
+		};
+
+		let mut all:Vec<&Record> = t.iter ().filter (|r| r.num>10 && r.str.starts_with (b"ab")).collect ();
+		all.sort_by_key (|r| (cmp::Reverse (r.num), cmp::Reverse (r.id)));
+		let want:Vec<u32> = all.iter ().skip (5).take (20).map (|r| r.id).collect ();
+		assert_eq! (ids ("select * from t where num > 10 and str like 'ab%' order by num desc limit 20 offset 5"),
+		            (all.len () as u32, want));
+
+		assert_eq! (ids ("select * from t where id >= 290 offset 7"), (10, vec![297,298,299]));
+		assert_eq! (ids ("select * from t where id >= 290 offset 10"), (10, vec![]));
+		assert_eq! (ids ("select * from t order by id desc limit 2 offset 4294967295"), (300, vec![]));
+		assert_eq! (ids ("select * from t limit 0"), (300, vec![]));
+
+		let (tot,recs)=t.query (&Query::parse ("select * from t").unwrap ()).unwrap ();
+		assert_eq! ((tot, recs.len () as u32), (300, 300));
+	}
+
+	#[test]
+	fn msg () {
+		let m=Query::msg ("select * from t", 7);
+		assert! (Request::from_bytes (&m).is_none ());
+		assert_eq! (Query::from_msg (&m), Some ((7, "select * from t")));
+
+		let mut t=Table::new ();
+		t.add (Record::new (1, 2, "x")).unwrap ();
+		let a=t.answer (7, "select * from t");
+		let r=Response::from_bytes (&a).unwrap ();
+		assert! (r.ok && {r.NS}==1 && a.len ()==RESP_SZ+REC_SZ);
+
+		let a=t.answer (9, "select count(*), sum(num), min(str), avg(id) from t where id > 5");
+		assert_eq! (str::from_utf8 (&a[RESP_SZ..]), Ok ("count(*)      sum(num)      min(str)      avg(id)\n0             0             -             -\n"));
+
+		let a=t.answer (8, "select");
+		assert! (!Response::from_bytes (&a).unwrap ().ok && a.len ()>RESP_SZ);
+	}
+}
@@ -0,0 +1,134 @@
+/* Snapshot of a table on disk, a plain dump of the records in primary key order:
+	magic  : [u8;4] ("RDB3", since the expiries are kept; "RDB2" is read as well, it has none)
+	tot    : u32
+	records: tot*REC_SZ raw bytes (same representation as on the wire)
+	nexp   : u32
+	expiry : nexp*(id u32, ms since the UNIX epoch u64)
+ */
+
+use std::{
+	fs::{self,File},
+	io::{self,BufReader,BufWriter,Read,Write},
+	path::Path,
+	ptr,
+	slice,
+	time::{Duration,UNIX_EPOCH}
+};
+
+use crate::*;
+
+const MAGIC:&[u8;4] = b"RDB3";
+const NO_TTL:&[u8;4] = b"RDB2";
+
+impl Table {
+	pub fn save (&self, w:&mut impl Write)->io::Result<()> {
+		w.write_all (MAGIC)?;
+		w.write_all (&self.tot.to_ne_bytes ())?;
+
+		for r in self.iter () {
+			let p=r as *const Record as *const u8;
+			w.write_all (unsafe { slice::from_raw_parts (p,REC_SZ) })?;
+		}
+
+		let exp:Vec<(u32,u64)> = self.ttl.iter ().collect ();
+		w.write_all (&(exp.len () as u32).to_ne_bytes ())?;
+		for (id,at) in exp {
+			w.write_all (&id.to_ne_bytes ())?;
+			w.write_all (&at.to_ne_bytes ())?;
+		}
+
+		w.flush ()
+	}
+
+	pub fn load (r:&mut impl Read)->io::Result<Self> {
+		let bad = |msg:&str| io::Error::new (io::ErrorKind::InvalidData, msg.to_string ());
+
+		let mut hdr=[0u8;8];
+		r.read_exact (&mut hdr)?;
+		if &hdr[..4]!=MAGIC && &hdr[..4]!=NO_TTL {
+			return Err (bad ("not a table snapshot"))
+		}
+		let tot=u32::from_ne_bytes (hdr[4..].try_into ().unwrap ());
+
+		let mut t=Table::new ();
+		let mut buf=[0u8;REC_SZ];
+		for _ in 0..tot {
+			r.read_exact (&mut buf)?;
+			let rec=unsafe { ptr::read_unaligned (buf.as_ptr () as *const Record) };
+
+			t.add (rec).map_err (|_| bad ("duplicate id in snapshot"))?;
+		}
+
+		if &hdr[..4]==MAGIC {
+			let mut n=[0u8;4];
+			r.read_exact (&mut n)?;
+			let mut buf=[0u8;12];
+			for _ in 0..u32::from_ne_bytes (n) {
+				r.read_exact (&mut buf)?;
+				let (id,at) = (u32::from_ne_bytes (buf[..4].try_into ().unwrap ()), u64::from_ne_bytes (buf[4..].try_into ().unwrap ()));
+				t.set_expiry (id, Some (UNIX_EPOCH + Duration::from_millis (at))).map_err (|_| bad ("expiry of no row in snapshot"))?;
+			}
+		}
+
+		Ok (t)
+	}
+
+	/* The snapshot is written aside, synced, and renamed over, the directory synced after that,
+	   so a crash (a power loss, even) never leaves a torn file behind */
+	pub fn save_to (&self, path:&Path)->io::Result<()> {
+		let tmp=path.with_extension ("tmp");
+
+		let mut w=BufWriter::new (File::create (&tmp)?);
+		self.save (&mut w)?;
+		w.into_inner ().map_err (|e| e.into_error ())?.sync_all ()?;
+		fs::rename (tmp, path)?;
+
+		let dir=path.parent ().filter (|d| !d.as_os_str ().is_empty ()).unwrap_or (Path::new ("."));
+		File::open (dir)?.sync_all ()
+	}
+
+	pub fn load_from (path:&Path)->io::Result<Self> {
+		Self::load (&mut BufReader::new (File::open (path)?))
+	}
+}
+
+#[cfg (test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn roundtrip () {
+		let mut t=Table::new ();
+		for id in [5,1,42,7] {
+			t.add (Record::gen (id, &mut rand::thread_rng ())).unwrap ();
+		}
+
+		let at=UNIX_EPOCH + Duration::from_secs (1<<40);
+		t.set_expiry (7, Some (at)).unwrap ();
+
+		let mut buf=Vec::new ();
+		t.save (&mut buf).unwrap ();
+		assert_eq! (buf.len (), 8 + 4*REC_SZ + 4 + 12);
+
+		let l=Table::load (&mut buf.as_slice ()).unwrap ();
+		assert_eq! (l.tot (), 4);
+		for (a,b) in t.iter ().zip (l.iter ()) {
+			assert_eq! ((a.id,a.num,a.str), (b.id,b.num,b.str));
+		}
+		assert_eq! ((l.expiry (7), l.expiry (5)), (Some (at), None));
+
+		// the older snapshots have no expiries
+		let mut old=buf[..8 + 4*REC_SZ].to_vec ();
+		old[..4].copy_from_slice (NO_TTL);
+		assert! (Table::load (&mut old.as_slice ()).is_ok_and (|l| l.tot ()==4 && l.expiry (7).is_none ()));
+
+		buf[0]=b'X';
+		assert! (Table::load (&mut buf.as_slice ()).is_err ());
+
+		// through a file, none left aside
+		let path=std::env::temp_dir ().join (format! ("rustdb-snapshot-{}.rdb", std::process::id ()));
+		t.save_to (&path).unwrap ();
+		assert! (Table::load_from (&path).is_ok_and (|l| l.tot ()==4) && !path.with_extension ("tmp").exists ());
+		fs::remove_file (path).unwrap ();
+	}
+}
@@ -0,0 +1,196 @@
+/* Expiring rows: a row may be given the time it expires at, after which it's hidden from the pages,
+	the queries, the aggregates and the groups, and from tot, until the reaper (Table::reap, run every now
+	and then) removes it with rm. A bulk delete takes it along but doesn't count it; rm, upd and set_expiry
+	still find it by its id.
+	The times are ms since the UNIX epoch, so that they're kept in the snapshots as they are.
+ */
+
+use std::{
+	collections::{BTreeSet,HashMap,HashSet},
+	time::{Duration,SystemTime,UNIX_EPOCH}
+};
+
+use crate::*;
+
+#[derive (Debug, Default)]
+pub(crate) struct Ttl {
+	by_time: BTreeSet<(u64,u32)>,  // (expiry, id)
+	by_id  : HashMap<u32,u64>
+}
+
+impl Ttl {
+	pub fn set (&mut self, id:u32, at:Option<u64>) {
+		if let Some (old)=self.by_id.remove (&id) {
+			self.by_time.remove (&(old,id));
+		}
+		if let Some (at)=at {
+			self.by_id.insert (id, at);
+			self.by_time.insert ((at,id));
+		}
+	}
+
+	// The ids of the rows expired by now
+	pub fn expired (&self, now:u64)->impl Iterator<Item=u32> + '_ {
+		self.by_time.range (..(now,u32::MAX)).map (|&(_,id)| id)
+	}
+
+	pub fn iter (&self)->impl Iterator<Item=(u32,u64)> + '_ {
+		self.by_id.iter ().map (|(&id,&at)| (id,at))
+	}
+}
+
+// The rows expired by now but not reaped yet, found once for a call: the records, to find them in an index by,
+// and their ids, to tell them in a scan
+#[derive (Debug, Default)]
+pub(crate) struct Gone {
+	pub recs: Vec<Arc<Record>>,
+	ids     : HashSet<u32>
+}
+
+impl Gone {
+	pub fn has (&self, r:&Record)->bool {
+		self.ids.contains (&r.id)
+	}
+}
+
+pub(crate) fn now ()->u64 {
+	ms (SystemTime::now ())
+}
+
+fn ms (t:SystemTime)->u64 {
+	t.duration_since (UNIX_EPOCH).map_or (0, |d| d.as_millis () as u64)
+}
+
+impl Table {
+	// Sets (or with None, clears) the time the row expires at, a change the stream has as well (for the replicas)
+	pub fn set_expiry (&mut self, id:u32, at:Option<SystemTime>)->Result<(),Error> {
+		self.fields[Column::Id as usize].search (&Record { id, ..Default::default () }).ok_or (Error::NotFound)?;
+		self.ttl.set (id, at.map (ms));
+		self.ver += 1;
+		self.cdc.publish (Change::Expiry { id, at: self.expiry (id) });
+		Ok (())
+	}
+
+	pub fn expiry (&self, id:u32)->Option<SystemTime> {
+		self.ttl.by_id.get (&id).map (|&at| UNIX_EPOCH + Duration::from_millis (at))
+	}
+
+	// When the next row expires
+	pub fn next_expiry (&self)->Option<SystemTime> {
+		self.ttl.by_time.first ().map (|&(at,_)| UNIX_EPOCH + Duration::from_millis (at))
+	}
+
+	// When the next of the rows not expired yet expires: the pages change then, though the table (and its ver) doesn't
+	pub fn next_change (&self)->Option<SystemTime> {
+		self.ttl.by_time.range ((now ()+1,0)..).next ().map (|&(at,_)| UNIX_EPOCH + Duration::from_millis (at))
+	}
+
+	// A row that expires in ttl
+	pub fn add_expiring (&mut self, rec:Record, ttl:Duration)->Result<(),Error> {
+		self.add (rec)?;
+		self.set_expiry (rec.id, Some (SystemTime::now () + ttl))
+	}
+
+	// Removes the expired rows, their number
+	pub fn reap (&mut self)->u32 {
+		let ids:Vec<u32> = self.ttl.expired (now ()).collect ();
+		for &id in &ids {
+			self.rm (id).expect ("An expiring row that isn't there");
+		}
+		ids.len () as u32
+	}
+
+	// The rows expired by now but not reaped yet
+	pub(crate) fn expired (&self)->Gone {
+		let ix=&self.fields[Column::Id as usize];
+		let recs:Vec<Arc<Record>> = self.ttl.expired (now ()).filter_map (|id| ix.search (&Record { id, ..Default::default () }).map (|n| n.elem.clone ())).collect ();
+		Gone { ids: recs.iter ().map (|r| r.id).collect (), recs }
+	}
+}
+
+#[cfg (test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn expiry () {
+		let mut t=Table::new ();
+		for id in 0..100 {
+			t.add (Record::new (id, id as i32 % 7, "")).unwrap ();
+		}
+		let past=SystemTime::now () - Duration::from_secs (1);
+		let gone:Vec<u32> = (0..100).filter (|id| id%9==4 || *id<3 || *id>96).collect ();
+		for &id in &gone {
+			t.set_expiry (id, Some (past)).unwrap ();
+		}
+		t.add_expiring (Record::new (100, 3, ""), Duration::from_secs (3600)).unwrap ();
+		assert! (t.set_expiry (1000, Some (past)).is_err ());
+		assert! (t.expiry (100).is_some () && t.expiry (5).is_none ());
+		assert! (t.next_expiry ()<=Some (past) && t.next_change ()==t.expiry (100));
+		assert_eq! (t.tot (), 101 - gone.len () as u32);
+
+		// every page, by positions and by cursors, in both directions, skips the expired rows
+		for col in [Column::Id, Column::Num] {
+			for bw in [false,true] {
+				let mut want:Vec<u32> = t.fields[col as usize].iter ().map (|r| r.id).filter (|id| !gone.contains (id)).collect ();
+				if bw {
+					want.reverse ();
+				}
+				let req=Request { bw, ..Request::new (col, 6) };
+				let NS=t.fetch_recs (req).0.NS;
+				assert_eq! (NS, want.len () as u32 - 5);
+				for CS in 0..NS {
+					let ids:Vec<u32> = t.fetch_recs (Request { CS, NS, ..req }).1.iter ().map (|r| r.id).collect ();
+					assert_eq! (ids, want[CS as usize..CS as usize+6], "{col:?} {bw} {CS}");
+
+					let key=*t.fetch_recs (Request { CS, NS, ..req }).1[0];
+					let ids:Vec<u32> = t.fetch_recs (Request { cur: Cursor::At, key, ..req }).1.iter ().map (|r| r.id).collect ();
+					assert_eq! (ids, want[CS as usize..CS as usize+6]);
+				}
+			}
+		}
+		let (tot,recs)=t.query (&Query::parse ("select * from t where num = 4").unwrap ()).unwrap ();
+		assert! (recs.iter ().all (|r| !gone.contains (&r.id)) && tot==recs.len () as u32);
+
+		// the aggregates and the groups leave them out whether they're answered off the index or by a scan
+		let live:Vec<&Record> = t.iter ().filter (|r| !gone.contains (&r.id)).collect ();
+		for expr in [None, Some ("num >= 2"), Some ("id < 50"), Some ("num >= 2 and str = ''")] {
+			let f=expr.map (|e| Filter::parse (e).unwrap ());
+			let want:Vec<&Record> = live.iter ().copied ().filter (|r| f.as_ref ().is_none_or (|f| f.eval (r))).collect ();
+			for col in [Column::Id, Column::Num] {
+				let agg=t.aggregate (col, f.as_ref ()).unwrap ();
+				let val=|r:&Record| if col==Column::Id {r.id as i64} else {r.num as i64};
+				assert_eq! ((agg.count as usize, agg.sum), (want.len (), Some (want.iter ().map (|r| val (r)).sum ())), "{expr:?} {col:?}");
+				assert_eq! (agg.min.map (|r| val (&r)), want.iter ().map (|r| val (r)).min (), "{expr:?} {col:?}");
+				assert_eq! (agg.max.map (|r| val (&r)), want.iter ().map (|r| val (r)).max (), "{expr:?} {col:?}");
+			}
+		}
+		let groups=t.group_by (Column::Id, 3);
+		assert_eq! (groups.iter ().map (|g| g.count).sum::<u32> (), t.tot ());
+		assert! (groups.iter ().all (|g| g.count>0) && groups[0].key.id==3);  // 0..3 are all gone
+		assert_eq! (t.group_by (Column::Num, 0).iter ().map (|g| g.count).sum::<u32> (), t.tot ());
+
+		// the reaper takes them out through rm, so the change stream sees it
+		let rx=t.changes (t.seq ()).unwrap ();
+		t.upd (Record::new (100, 4, "")).unwrap ();  // keeps its expiry
+		assert_eq! (t.reap (), gone.len () as u32);
+		assert_eq! ((t.tot (), t.reap ()), (101 - gone.len () as u32, 0));
+		assert_eq! (rx.try_iter ().count (), 1 + gone.len ());
+		assert! (t.expiry (100).is_some () && t.expiry (gone[0]).is_none ());
+
+		t.rm (100).unwrap ();
+		assert! (t.expiry (100).is_none ());
+
+		// a bulk delete takes the expired rows along, without counting them
+		let mut t=Table::new ();
+		for id in 0..10 {
+			t.add (Record::new (id, 0, "")).unwrap ();
+		}
+		t.set_expiry (2, Some (past)).unwrap ();
+		t.set_expiry (3, Some (past)).unwrap ();
+		assert_eq! ((t.delete_range (Column::Id, ..Record::new (5, 0, "")), t.tot (), t.reap ()), (3, 5, 0));
+		t.set_expiry (6, Some (past)).unwrap ();
+		t.set_expiry (9, Some (past)).unwrap ();
+		assert_eq! ((t.delete_where (&Filter::parse ("id > 5 and num = 0").unwrap ()), t.tot (), t.reap ()), (2, 1, 0));
+	}
+}
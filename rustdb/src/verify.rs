@@ -0,0 +1,162 @@
+/* Integrity checks of a table, for the tests and for VERIFY TABLE: every index walked along all its levels,
+	its order, the prev links, the fingers and the sums checked against the rows they span; then the indexes
+	against each other, all of them having the same rows (the very same Arcs), as many as tot.
+	It takes a walk of every index, under the read lock.
+ */
+
+use std::collections::HashSet;
+
+use crate::*;
+
+impl Table {
+	// Ok, or the first inconsistency found
+	pub fn verify (&self)->Result<(),String> {
+		let rows=|ix:&Index| ix.iter ().map (Arc::as_ptr).collect::<HashSet<_>> ();
+		let ids=rows (&self.fields[Column::Id as usize]);
+
+		for ix in self.indexes () {
+			let name=index_name (ix.col, ix.expr);
+			ix.verify ().map_err (|e| format! ("Index {name}: {e}"))?;
+			if ix.len!=self.tot {
+				return Err (format! ("Index {name}: {} rows, tot is {}", ix.len, self.tot))
+			}
+			if ix.col!=Column::Id || ix.expr!=Expr::Field {
+				let own=rows (ix);
+				if own!=ids {
+					let id=|r:&*const Record| unsafe { (**r).id };
+					let (a,b) = (own.difference (&ids).map (id).next (), ids.difference (&own).map (id).next ());
+					return Err (format! ("Index {name}: not the rows of the id index (id {:?} is only here, {:?} missing)", a, b))
+				}
+			}
+		}
+		if let Some ((id,_))=self.ttl.iter ().find (|&(id,_)| self.fields[Column::Id as usize].search (&Record { id, ..Default::default () }).is_none ()) {
+			return Err (format! ("Expiry of no row: id {id}"))
+		}
+
+		Ok (())
+	}
+}
+
+impl Index {
+	pub(crate) fn verify (&self)->Result<(),String> {
+		unsafe {
+			let head=&*self.head.0;
+			for l in 0..head.lvls () {
+				if (l<self.l)==head.nxt (l).is_null () {
+					return Err (format! ("{} levels, the head's level {l} is {}", self.l, if l<self.l {"empty"} else {"not"}))
+				}
+			}
+
+			// the last node on each level, its position, the sum of the values after it
+			let mut last=[self.head.0; MAXLVL+1];
+			let mut at  =[0u32; MAXLVL+1];
+			let mut acc =[0i64; MAXLVL+1];
+			let (mut n, mut mem, mut prev) = (0u32, head.size (), self.head.0);
+
+			let mut cur=head.nxt (0);
+			while !cur.is_null () {
+				let node=&*cur;
+				let id=node.elem.id;
+				n += 1;
+				mem += node.size ();
+
+				if node.prev.0!=prev {
+					return Err (format! ("id {id}: prev isn't the row before"))
+				}
+				// equal keys only in a bare index, a table's ids are unique (the Arcs are checked for that)
+				if prev!=self.head.0 && self.comp (&(*prev).elem, &node.elem).is_gt () {
+					return Err (format! ("id {id}: out of order"))
+				}
+				if node.lvls ()==0 || node.lvls ()>self.l {
+					return Err (format! ("id {id}: {} levels of {}", node.lvls (), self.l))
+				}
+
+				let v=if self.sums {self.val (&node.elem)} else {0};
+				for l in 0..self.l {
+					acc[l] = acc[l].wrapping_add (v);
+					if l>=node.lvls () {
+						continue
+					}
+					let p=&*last[l];
+					if p.nxt (l)!=cur {
+						return Err (format! ("id {id}: not linked at level {l}"))
+					}
+					if p.fng (l)!=n-at[l] {
+						return Err (format! ("id {id}: the finger to it at level {l} is {}, the distance {}", p.fng (l), n-at[l]))
+					}
+					if self.sums && p.sum (l)!=acc[l] {
+						return Err (format! ("id {id}: the sum to it at level {l} is {}, that of the rows {}", p.sum (l), acc[l]))
+					}
+					(last[l], at[l], acc[l]) = (cur, n, 0);
+				}
+				prev = cur;
+				cur = node.nxt (0);
+			}
+
+			for l in 0..self.l {
+				if !(*last[l]).nxt (l).is_null () {
+					return Err (format! ("level {l} goes past the last row"))
+				}
+			}
+			if n!=self.len {
+				return Err (format! ("{n} rows, {} counted", self.len))
+			}
+			if mem!=self.mem {
+				return Err (format! ("{mem} bytes of nodes, {} counted", self.mem))
+			}
+		}
+
+		Ok (())
+	}
+}
+
+#[cfg (test)]
+mod tests {
+	use super::*;
+	use rand::{Rng,SeedableRng,rngs::StdRng};
+
+	#[test]
+	fn verify () {
+		let mut t=Table::with_seed (3);
+		let rng=&mut StdRng::seed_from_u64 (3);
+		t.create_index (Column::Num, Expr::Abs).unwrap ();
+		assert_eq! (t.verify (), Ok (()));
+		for id in 0..2000 {
+			let r=Record::new (id, rng.gen_range (-50..50), &format! ("{}", rng.gen_range (0..30)));
+			t.add (if id%7==0 {r.null (Column::Num)} else {r}).unwrap ();
+			match rng.gen_range (0..4) {
+				0 => { let _=t.rm (rng.gen_range (0..=id)); }
+				1 => { let _=t.upd (Record::new (rng.gen_range (0..=id), rng.gen_range (-50..50), "u")); }
+				_ => {}
+			}
+		}
+		t.delete_range (Column::Num, Record::new (0,-10,"")..Record::new (0,10,""));
+		t.set_expiry (1, Some (std::time::SystemTime::now ())).unwrap_or_default ();
+		assert_eq! (t.verify (), Ok (()));
+
+		// each kind of damage is found
+		let ix=&t.fields[Column::Num as usize];
+		let (first,second) = unsafe { let a=(*ix.head.0).nxt (0); (a, (*a).nxt (0)) };
+		unsafe {
+			(*first).set_fng (0, 2);
+			assert! (t.verify ().is_err_and (|e| e.starts_with ("Index num: id") && e.contains ("finger to it at level 0 is 2")), "{:?}", t.verify ());
+			(*first).set_fng (0, 1);
+
+			(*first).set_sum (0, (*first).sum (0)+1);
+			assert! (t.verify ().is_err_and (|e| e.contains ("the sum to it at level 0")));
+			(*first).set_sum (0, (*first).sum (0)-1);
+
+			let p=(*second).prev.0;
+			(*second).prev.0 = ix.head.0;
+			assert! (t.verify ().is_err_and (|e| e.contains ("prev isn't")));
+			(*second).prev.0 = p;
+		}
+		assert_eq! (t.verify (), Ok (()));
+
+		let r=t.fields[0].iter ().nth (5).unwrap ().clone ();
+		t.exprs[0].1.delete (&r, true).unwrap ();
+		assert! (t.verify ().is_err_and (|e| e.starts_with ("Index abs(num): 1") && e.contains ("rows, tot is")));
+		t.exprs[0].1.insert (Arc::new (*r));
+		assert! (t.verify ().is_err_and (|e| e.contains (&format! ("id Some({}) is only here", r.id))));
+	}
+}
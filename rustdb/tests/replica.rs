@@ -0,0 +1,80 @@
+/* A primary and its replica, two servers on loopback: the replica connects while the primary is still
+   being filled, so it gets both a full copy and the changes after it, and ends up with the same table.
+   Then the primary restarts with other rows, more of them than the seqs the replica has got to:
+   it's another stream, so the replica copies the table over again rather than carrying on with it */
+
+use std::{
+	net::UdpSocket,
+	process::{Child,Command,Stdio},
+	thread,
+	time::{Duration,Instant}
+};
+
+use rustdb::*;
+
+const PRIMARY:&str = "127.0.0.1:50971";
+const REPLICA:&str = "127.0.0.1:50972";
+
+struct Srv (Child);
+
+impl Srv {
+	fn start (args:&[&str])->Self {
+		Srv (Command::new (env! ("CARGO_BIN_EXE_srv")).args (args).arg ("-b")
+		     .stdout (Stdio::null ()).stderr (Stdio::null ()).spawn ().unwrap ())
+	}
+}
+
+impl Drop for Srv {
+	fn drop (&mut self) {
+		let _=self.0.kill ();
+		let _=self.0.wait ();
+	}
+}
+
+// The text of the answer to a query, if it's ok
+fn ask (sock:&UdpSocket, addr:&str, text:&str)->Option<String> {
+	sock.send_to (&Query::msg (text, 1), addr).ok ()?;
+	let mut buf=[0;4096];
+	let (n,_) = sock.recv_from (&mut buf).ok ()?;
+	let resp=Response::from_bytes (&buf[..n])?;
+	resp.ok.then (|| String::from_utf8_lossy (&buf[RESP_SZ..n]).to_string ())
+}
+
+// Until both servers answer the same, with the rows there should be
+fn caught_up (sock:&UdpSocket, rows:&str) {
+	let sums="select count(*), sum(num), min(str), max(id) from t";
+	let start=Instant::now ();
+	loop {
+		let (p,r) = (ask (sock, PRIMARY, sums), ask (sock, REPLICA, sums));
+		if p.is_some () && p==r && p.as_deref ().is_some_and (|p| p.contains (rows)) {
+			break
+		}
+		assert! (start.elapsed ()<Duration::from_secs (60), "the replica hasn't caught up: {p:?} {r:?}");
+		thread::sleep (Duration::from_millis (200));
+	}
+}
+
+#[test]
+fn replica () {
+	let _r=Srv::start (&["-l", REPLICA, "-P", PRIMARY]);
+	thread::sleep (Duration::from_millis (200));
+	let p=Srv::start (&["-l", PRIMARY, "-d", "gen:100", "-f", "20000", "-w", "0"]);
+
+	let sock=UdpSocket::bind ("127.0.0.1:0").unwrap ();
+	sock.set_read_timeout (Some (Duration::from_millis (500))).unwrap ();
+	caught_up (&sock, "20100");
+
+	thread::sleep (Duration::from_millis (1500));  // for the head to come
+	let status=ask (&sock, REPLICA, "show replication").unwrap ();
+	assert! (status.contains (" 0 behind"), "{status}");
+	assert_eq! (ask (&sock, REPLICA, "create table x"), None);
+	for addr in [PRIMARY, REPLICA] {
+		assert! (ask (&sock, addr, "verify table t").is_some_and (|a| a.contains ("consistent: 20100 rows")));
+	}
+	assert_eq! (ask (&sock, PRIMARY, "show replication"), None);
+
+	drop (p);
+	let _p=Srv::start (&["-l", PRIMARY, "-d", "gen:100", "-f", "25000", "-w", "0"]);
+	caught_up (&sock, "25100");
+	assert! (ask (&sock, REPLICA, "verify table t").is_some_and (|a| a.contains ("consistent: 25100 rows")));
+}